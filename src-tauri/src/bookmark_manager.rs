@@ -0,0 +1,163 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use uuid::Uuid;
+
+use crate::cbz_viewer::CbzViewer;
+use crate::errors::AppError;
+use crate::file_manager::{FileManager, BOOKMARKS_FILE};
+use crate::library::Library;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bookmark {
+    pub id: String,
+    pub page: usize,
+    pub label: Option<String>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LibraryBookmark {
+    pub file_name: String,
+    pub title: String,
+    pub bookmark: Bookmark,
+}
+
+pub struct BookmarkManager;
+
+impl BookmarkManager {
+    fn normalize(text: Option<String>) -> Option<String> {
+        text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())
+    }
+
+    fn check_page(fm: &FileManager, file_name: &str, page: usize) -> Result<(), AppError> {
+        let full_path = fm.get_full_path(file_name)?;
+        let page_count = CbzViewer::get_image_list(&full_path.to_string_lossy())?.len();
+
+        if page >= page_count {
+            return Err(AppError::invalid_input(format!(
+                "Page {} out of range ({} pages)",
+                page, page_count
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn list(fm: &FileManager, file_name: &str) -> Result<Vec<Bookmark>, AppError> {
+        let mut bookmarks: Vec<Bookmark> = fm.read_sidecar(file_name, BOOKMARKS_FILE)?;
+        bookmarks.sort_by_key(|bookmark| (bookmark.page, bookmark.created_at));
+        Ok(bookmarks)
+    }
+
+    pub fn add(
+        fm: &FileManager,
+        file_name: &str,
+        page: usize,
+        label: Option<String>,
+        note: Option<String>,
+    ) -> Result<Bookmark, AppError> {
+        Self::check_page(fm, file_name, page)?;
+        let mut bookmarks = Self::list(fm, file_name)?;
+
+        let bookmark = Bookmark {
+            id: Uuid::new_v4().to_string(),
+            page,
+            label: Self::normalize(label),
+            note: Self::normalize(note),
+            created_at: Utc::now(),
+        };
+
+        bookmarks.push(bookmark.clone());
+        fm.write_sidecar(file_name, BOOKMARKS_FILE, &bookmarks)?;
+        Ok(bookmark)
+    }
+
+    /// Replaces the label and note of a bookmark, and moves it when `page` is given.
+    pub fn edit(
+        fm: &FileManager,
+        file_name: &str,
+        id: &str,
+        page: Option<usize>,
+        label: Option<String>,
+        note: Option<String>,
+    ) -> Result<Bookmark, AppError> {
+        let mut bookmarks = Self::list(fm, file_name)?;
+        let bookmark = bookmarks
+            .iter_mut()
+            .find(|bookmark| bookmark.id == id)
+            .ok_or_else(|| AppError::invalid_input(format!("Bookmark not found: {}", id)))?;
+
+        if let Some(page) = page {
+            Self::check_page(fm, file_name, page)?;
+            bookmark.page = page;
+        }
+        bookmark.label = Self::normalize(label);
+        bookmark.note = Self::normalize(note);
+        let edited = bookmark.clone();
+
+        fm.write_sidecar(file_name, BOOKMARKS_FILE, &bookmarks)?;
+        Ok(edited)
+    }
+
+    pub fn delete(fm: &FileManager, file_name: &str, id: &str) -> Result<(), AppError> {
+        let mut bookmarks = Self::list(fm, file_name)?;
+        let count = bookmarks.len();
+        bookmarks.retain(|bookmark| bookmark.id != id);
+
+        if bookmarks.len() == count {
+            return Err(AppError::invalid_input(format!(
+                "Bookmark not found: {}",
+                id
+            )));
+        }
+
+        fm.write_sidecar(file_name, BOOKMARKS_FILE, &bookmarks)
+    }
+
+    /// Moves bookmarks to the new index of their page after the pages of a
+    /// comic were rewritten, dropping those whose page was removed.
+    /// `new_indexes` holds the new index of each old page.
+    pub fn remap_pages(
+        fm: &FileManager,
+        file_name: &str,
+        new_indexes: &[Option<usize>],
+    ) -> Result<(), AppError> {
+        let bookmarks: Vec<Bookmark> = Self::list(fm, file_name)?
+            .into_iter()
+            .filter_map(|mut bookmark| {
+                bookmark.page = new_indexes.get(bookmark.page).copied().flatten()?;
+                Some(bookmark)
+            })
+            .collect();
+
+        fm.write_sidecar(file_name, BOOKMARKS_FILE, &bookmarks)
+    }
+
+    /// Every bookmark in the library, newest first.
+    pub fn list_all(library: &Library) -> Result<Vec<LibraryBookmark>, AppError> {
+        let mut all = Vec::new();
+
+        for file_name in library.list_files() {
+            let bookmarks = Self::list(&library.files, &file_name)?;
+            if bookmarks.is_empty() {
+                continue;
+            }
+
+            let title = library
+                .metadata(&file_name)
+                .map(|info| info.title)
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| file_name.clone());
+
+            all.extend(bookmarks.into_iter().map(|bookmark| LibraryBookmark {
+                file_name: file_name.clone(),
+                title: title.clone(),
+                bookmark,
+            }));
+        }
+
+        all.sort_by_key(|entry| Reverse(entry.bookmark.created_at));
+        Ok(all)
+    }
+}
@@ -1,11 +1,19 @@
 use base64::engine::general_purpose;
 use base64::Engine;
+use quick_xml::events::Event;
+use quick_xml::Reader;
 use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, Cursor, Read};
 use zip::ZipArchive;
 
-#[derive(Serialize, Deserialize)]
+use crate::errors::AppError;
+
+/// How much of a page is read to find its size. Image headers sit at the
+/// start, though JPEGs with a large EXIF block can push them further.
+const DIMENSIONS_PREFIX: u64 = 64 * 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ComicInfo {
     pub title: String,
     pub series: String,
@@ -18,11 +26,153 @@ pub struct ComicInfo {
     pub writer: String,
     pub publisher: String,
     pub page_count: String,
+    #[serde(default)]
+    pub manga: String,
+}
+
+pub const COMIC_INFO_TEMPLATE: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<ComicInfo xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+</ComicInfo>
+"#;
+
+impl ComicInfo {
+    /// Writes the fields into a ComicInfo.xml document. Starting from an
+    /// existing document keeps the elements this struct does not cover.
+    pub fn to_xml(&self, base: Option<&str>) -> String {
+        let mut xml = base.unwrap_or(COMIC_INFO_TEMPLATE).to_string();
+
+        for (tag, value) in self.elements() {
+            Self::set_element(&mut xml, tag, value);
+        }
+
+        xml
+    }
+
+    /// The ComicInfo.xml element name of each field, with its value.
+    pub fn elements(&self) -> [(&'static str, &str); 12] {
+        [
+            ("Title", &self.title),
+            ("Series", &self.series),
+            ("Number", &self.number),
+            ("Volume", &self.volume),
+            ("Summary", &self.summary),
+            ("Year", &self.year),
+            ("Month", &self.month),
+            ("Day", &self.day),
+            ("Writer", &self.writer),
+            ("Publisher", &self.publisher),
+            ("PageCount", &self.page_count),
+            ("Manga", &self.manga),
+        ]
+    }
+
+    /// Replaces the text of an element, or adds the element at the end when
+    /// the document does not have it and there is a value to write.
+    pub fn set_element(xml: &mut String, tag: &str, value: &str) {
+        let open = format!("<{}>", tag);
+        let close = format!("</{}>", tag);
+        let value = quick_xml::escape::escape(value);
+
+        if let (Some(start), Some(end)) = (xml.find(&open), xml.find(&close)) {
+            if start < end {
+                xml.replace_range(start + open.len()..end, &value);
+                return;
+            }
+        }
+
+        if let Some(end) = xml.rfind("</ComicInfo>") {
+            if !value.is_empty() {
+                xml.insert_str(end, &format!("  {}{}{}\n", open, value, close));
+            }
+        }
+    }
+
+    /// Replaces the `<Pages>` element with one entry per page that has a type or
+    /// is a double page. The element is removed when no page needs an entry.
+    pub fn set_pages(xml: &mut String, pages: &[ComicPageInfo]) {
+        let mut element = String::from("<Pages>\n");
+        for page in pages {
+            if page.page_type.is_empty() && !page.double_page {
+                continue;
+            }
+
+            element.push_str(&format!("    <Page Image=\"{}\"", page.image));
+            if !page.page_type.is_empty() {
+                element.push_str(&format!(
+                    " Type=\"{}\"",
+                    quick_xml::escape::escape(&page.page_type)
+                ));
+            }
+            if page.double_page {
+                element.push_str(" DoublePage=\"True\"");
+            }
+            element.push_str(" />\n");
+        }
+        element.push_str("  </Pages>");
+        let has_entries = element.contains("<Page ");
+
+        if let Some(start) = xml.find("<Pages") {
+            let end = match xml[start..].find('>') {
+                Some(close) if xml[..start + close].ends_with('/') => Some(start + close + 1),
+                _ => xml.find("</Pages>").map(|end| end + "</Pages>".len()),
+            };
+            if let Some(end) = end {
+                xml.replace_range(start..end, if has_entries { &element } else { "" });
+                return;
+            }
+        }
+
+        if has_entries {
+            if let Some(end) = xml.rfind("</ComicInfo>") {
+                xml.insert_str(end, &format!("  {}\n", element));
+            }
+        }
+    }
+}
+
+/// A `<Page>` entry from the `<Pages>` element of ComicInfo.xml.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ComicPageInfo {
+    pub image: usize,
+    pub page_type: String,
+    pub double_page: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PageDimensions {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
 }
 
 pub struct CbzViewer;
 
 impl CbzViewer {
+    /// Whether an archive entry is a page image the viewer can show.
+    pub fn is_image(name: &str) -> bool {
+        let name_lower = name.to_lowercase();
+        name_lower.ends_with(".jpg")
+            || name_lower.ends_with(".png")
+            || name_lower.ends_with(".webp")
+    }
+
+    /// The image subtype for a data URL of a page.
+    pub fn mime_subtype(name: &str) -> &'static str {
+        let name_lower = name.to_lowercase();
+        if name_lower.ends_with(".png") {
+            "png"
+        } else if name_lower.ends_with(".webp") {
+            "webp"
+        } else {
+            "jpeg"
+        }
+    }
+
+    pub fn open_archive(cbz_path: &str) -> Result<ZipArchive<BufReader<File>>, AppError> {
+        let file = File::open(cbz_path).map_err(|e| AppError::fs(cbz_path, e))?;
+        ZipArchive::new(BufReader::new(file)).map_err(|e| AppError::archive_corrupt(cbz_path, e))
+    }
+
     fn extract_tag_value(contents: &str, tag: &str) -> Option<String> {
         let start_tag = format!("<{}>", tag);
         let end_tag = format!("</{}>", tag);
@@ -31,19 +181,19 @@ impl CbzViewer {
         Some(contents[start..end].trim().to_string())
     }
 
-    pub fn read_comic_info(cbz_path: &str) -> Result<ComicInfo, String> {
-        let file = File::open(cbz_path).map_err(|e| e.to_string())?;
-        let mut archive: ZipArchive<BufReader<File>> =
-            ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    pub fn read_comic_info(cbz_path: &str) -> Result<ComicInfo, AppError> {
+        let mut archive = Self::open_archive(cbz_path)?;
 
         for i in 0..archive.len() {
-            let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
+            let mut file = archive
+                .by_index(i)
+                .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
             let name = file.name().to_lowercase();
 
             if name.ends_with("comicinfo.xml") {
                 let mut contents = String::new();
                 file.read_to_string(&mut contents)
-                    .map_err(|e| e.to_string())?;
+                    .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
 
                 let title = Self::extract_tag_value(&contents, "Title").unwrap_or_default();
                 let series = Self::extract_tag_value(&contents, "Series").unwrap_or_default();
@@ -58,6 +208,7 @@ impl CbzViewer {
                 let publisher = Self::extract_tag_value(&contents, "Publisher").unwrap_or_default();
                 let page_count: String =
                     Self::extract_tag_value(&contents, "PageCount").unwrap_or_default();
+                let manga = Self::extract_tag_value(&contents, "Manga").unwrap_or_default();
 
                 return Ok(ComicInfo {
                     title,
@@ -71,101 +222,217 @@ impl CbzViewer {
                     writer,
                     publisher,
                     page_count,
+                    manga,
                 });
             }
         }
 
-        Err("ComicInfo.xml not found".into())
+        Err(AppError::FileNotFound {
+            path: format!("{}/ComicInfo.xml", cbz_path),
+        })
     }
 
-    pub fn extract_cover_image(cbz_path: &str) -> Result<Option<String>, String> {
-        let file = File::open(cbz_path).map_err(|e| e.to_string())?;
-        let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
-        let mut archives = Vec::new();
+    pub fn read_page_info(cbz_path: &str) -> Result<Vec<ComicPageInfo>, AppError> {
+        let mut archive = Self::open_archive(cbz_path)?;
 
+        let mut contents = String::new();
         for i in 0..archive.len() {
-            let file = archive.by_index(i).map_err(|e| e.to_string())?;
-            archives.push(file.name().to_string());
+            let mut file = archive
+                .by_index(i)
+                .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+            if file.name().to_lowercase().ends_with("comicinfo.xml") {
+                file.read_to_string(&mut contents)
+                    .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+                break;
+            }
         }
 
-        archives.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()));
-
-        for name in archives {
-            let mut file = archive.by_name(&name).map_err(|e| e.to_string())?;
-            let name_lower = name.to_lowercase();
-
-            if name_lower.ends_with(".jpg") || name_lower.ends_with(".png") {
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer).map_err(|e| e.to_string())?;
-                let encoded = general_purpose::STANDARD.encode(buffer);
-                return Ok(Some(format!(
-                    "data:image/{};base64,{}",
-                    if name_lower.ends_with(".png") {
-                        "png"
-                    } else {
-                        "jpeg"
-                    },
-                    encoded
-                )));
+        let mut pages = Vec::new();
+        let mut reader = Reader::from_str(&contents);
+
+        loop {
+            let event = reader
+                .read_event()
+                .map_err(|e| AppError::metadata_parse(cbz_path, e))?;
+            match event {
+                Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"Page" => {
+                    let mut page = ComicPageInfo::default();
+                    let mut has_image = false;
+
+                    for attr in e.attributes().flatten() {
+                        let value = attr
+                            .unescape_value()
+                            .map_err(|e| AppError::metadata_parse(cbz_path, e))?;
+                        match attr.key.as_ref() {
+                            b"Image" => {
+                                if let Ok(image) = value.trim().parse() {
+                                    page.image = image;
+                                    has_image = true;
+                                }
+                            }
+                            b"Type" => page.page_type = value.to_string(),
+                            b"DoublePage" => page.double_page = value.eq_ignore_ascii_case("true"),
+                            _ => {}
+                        }
+                    }
+
+                    if has_image {
+                        pages.push(page);
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
             }
         }
 
-        Ok(None)
+        Ok(pages)
     }
 
-    pub fn get_image_list(cbz_path: &str) -> Result<Vec<String>, String> {
-        let file = File::open(cbz_path).map_err(|e| e.to_string())?;
-        let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    /// Reads the size of every page from its image header, in page order. Only
+    /// the start of a page is read unless its header lies beyond it.
+    pub fn get_page_dimensions(cbz_path: &str) -> Result<Vec<PageDimensions>, AppError> {
+        let images_list = Self::get_image_list(cbz_path)?;
 
-        let mut images_path = Vec::new();
+        let mut archive = Self::open_archive(cbz_path)?;
 
-        let mut entries: Vec<_> = (0..archive.len()).collect();
-        entries.sort_by_key(|&i| archive.by_index(i).unwrap().name().to_lowercase());
+        let mut dimensions = Vec::with_capacity(images_list.len());
 
-        for i in entries {
-            let file: zip::read::ZipFile<'_, BufReader<File>> =
-                archive.by_index(i).map_err(|e| e.to_string())?;
-            let name = file.name().to_lowercase();
+        for name in images_list {
+            let mut file = archive
+                .by_name(&name)
+                .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+            let mut buffer = Vec::new();
+            (&mut file)
+                .take(DIMENSIONS_PREFIX)
+                .read_to_end(&mut buffer)
+                .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+
+            let read_dimensions = |buffer: &[u8]| {
+                image::ImageReader::new(Cursor::new(buffer))
+                    .with_guessed_format()
+                    .map_err(|e| AppError::archive_corrupt(cbz_path, e))?
+                    .into_dimensions()
+                    .map_err(|e| AppError::from_image(format!("{}/{}", cbz_path, name), e))
+            };
+            let (width, height) = match read_dimensions(&buffer) {
+                Ok(dimensions) => dimensions,
+                Err(_) if buffer.len() as u64 == DIMENSIONS_PREFIX => {
+                    file.read_to_end(&mut buffer)
+                        .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+                    read_dimensions(&buffer)?
+                }
+                Err(e) => return Err(e),
+            };
+
+            dimensions.push(PageDimensions {
+                name,
+                width,
+                height,
+            });
+        }
+
+        Ok(dimensions)
+    }
+
+    /// Returns the cover as a data URL: the page ComicInfo.xml marks as
+    /// FrontCover, or the first page when none is marked.
+    pub fn extract_cover_image(cbz_path: &str) -> Result<Option<String>, AppError> {
+        let images = Self::get_image_list(cbz_path)?;
+        let front_cover = Self::read_page_info(cbz_path)
+            .ok()
+            .and_then(|pages| {
+                pages
+                    .into_iter()
+                    .find(|page| page.page_type.eq_ignore_ascii_case("FrontCover"))
+            })
+            .and_then(|page| images.get(page.image));
 
-            if name.ends_with(".jpg") || name.ends_with(".png") {
-                images_path.push(file.name().to_string());
+        let Some(name) = front_cover.or(images.first()) else {
+            return Ok(None);
+        };
+
+        let mut archive = Self::open_archive(cbz_path)?;
+        let mut file = archive
+            .by_name(name)
+            .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)
+            .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+
+        Ok(Some(format!(
+            "data:image/{};base64,{}",
+            Self::mime_subtype(name),
+            general_purpose::STANDARD.encode(buffer)
+        )))
+    }
+
+    /// Puts entry names in page order.
+    pub fn sort_pages(names: &mut [String]) {
+        names.sort_by(|a, b| natord::compare_ignore_case(a, b));
+    }
+
+    pub fn get_image_list(cbz_path: &str) -> Result<Vec<String>, AppError> {
+        let mut archive = Self::open_archive(cbz_path)?;
+
+        let mut images_path = Vec::new();
+
+        let mut names = Vec::with_capacity(archive.len());
+        for i in 0..archive.len() {
+            let file = archive
+                .by_index(i)
+                .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+            names.push(file.name().to_string());
+        }
+        Self::sort_pages(&mut names);
+
+        for name in names {
+            if Self::is_image(&name) {
+                images_path.push(name);
             }
         }
 
         Ok(images_path)
     }
 
-    pub fn load_image_by_index(cbz_path: &str, image_index: usize) -> Result<String, String> {
-        let file = File::open(cbz_path).map_err(|e| e.to_string())?;
-        let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
-
+    pub fn read_image_bytes(
+        cbz_path: &str,
+        image_index: usize,
+    ) -> Result<(String, Vec<u8>), AppError> {
         let images_list = Self::get_image_list(cbz_path)?;
 
-        if images_list.len() == 0 {
-            return Err(format!("No images found in archive: {}", cbz_path));
+        if images_list.is_empty() {
+            return Err(AppError::archive_corrupt(
+                cbz_path,
+                "No images found in archive",
+            ));
         }
 
-        let image_name = &images_list[image_index];
+        let image_name = images_list.get(image_index).ok_or_else(|| {
+            AppError::invalid_input(format!(
+                "Image index {} out of range ({} images)",
+                image_index,
+                images_list.len()
+            ))
+        })?;
 
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
+        let mut archive = Self::open_archive(cbz_path)?;
+        let mut file = archive
+            .by_name(image_name)
+            .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
 
-            if file.name() == image_name {
-                let mut buffer = Vec::new();
-                file.read_to_end(&mut buffer).map_err(|e| e.to_string())?;
-                let encoded = general_purpose::STANDARD.encode(buffer);
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)
+            .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
 
-                let name_lower = image_name.to_lowercase();
-                let mime_type = if name_lower.ends_with(".png") {
-                    "png"
-                } else {
-                    "jpeg"
-                };
+        Ok((image_name.to_string(), buffer))
+    }
 
-                return Ok(format!("data:image/{};base64,{}", mime_type, encoded).to_string());
-            }
-        }
+    pub fn load_image_by_index(cbz_path: &str, image_index: usize) -> Result<String, AppError> {
+        let (image_name, buffer) = Self::read_image_bytes(cbz_path, image_index)?;
+        let encoded = general_purpose::STANDARD.encode(buffer);
+
+        let mime_type = Self::mime_subtype(&image_name);
 
-        Err(format!("Image '{}' not found in archive", image_name))
+        Ok(format!("data:image/{};base64,{}", mime_type, encoded))
     }
 }
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::image_processor::FitMode;
+
+pub const DEFAULT_CROP_TOLERANCE: u8 = 16;
+pub const DEFAULT_SPREAD_THRESHOLD: f32 = 1.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadingDirection {
+    #[default]
+    #[serde(rename = "ltr")]
+    LeftToRight,
+    #[serde(rename = "rtl")]
+    RightToLeft,
+    /// Long-strip mode: pages are stacked vertically and scrolled.
+    #[serde(rename = "vertical")]
+    Vertical,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageMode {
+    #[default]
+    Single,
+    Double,
+}
+
+/// The view settings that apply when a comic is opened, after resolving
+/// defaults, metadata, series and per-comic preferences.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ComicSettings {
+    pub reading_direction: ReadingDirection,
+    pub fit_mode: FitMode,
+    pub page_mode: PageMode,
+    pub zoom: f32,
+    pub crop_borders: bool,
+    pub crop_tolerance: u8,
+    pub split_spreads: bool,
+    /// Width / height ratio above which a page is treated as a double-page spread.
+    pub spread_threshold: f32,
+    /// Shifts the page pairing of two-page mode by this many pages.
+    pub spread_offset: usize,
+}
+
+impl Default for ComicSettings {
+    fn default() -> Self {
+        Self {
+            reading_direction: ReadingDirection::default(),
+            fit_mode: FitMode::default(),
+            page_mode: PageMode::default(),
+            zoom: 1.0,
+            crop_borders: false,
+            crop_tolerance: DEFAULT_CROP_TOLERANCE,
+            split_spreads: false,
+            spread_threshold: DEFAULT_SPREAD_THRESHOLD,
+            spread_offset: 0,
+        }
+    }
+}
+
+/// View preferences stored globally, per series or per comic. Unset fields
+/// fall through to the next, less specific level.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ViewPreferences {
+    pub reading_direction: Option<ReadingDirection>,
+    pub fit_mode: Option<FitMode>,
+    pub page_mode: Option<PageMode>,
+    pub zoom: Option<f32>,
+    pub crop_borders: Option<bool>,
+    pub crop_tolerance: Option<u8>,
+    pub split_spreads: Option<bool>,
+    pub spread_threshold: Option<f32>,
+    pub spread_offset: Option<usize>,
+}
+
+impl ViewPreferences {
+    pub fn apply_to(&self, settings: &mut ComicSettings) {
+        if let Some(reading_direction) = self.reading_direction {
+            settings.reading_direction = reading_direction;
+        }
+        if let Some(fit_mode) = self.fit_mode {
+            settings.fit_mode = fit_mode;
+        }
+        if let Some(page_mode) = self.page_mode {
+            settings.page_mode = page_mode;
+        }
+        if let Some(zoom) = self.zoom {
+            settings.zoom = zoom;
+        }
+        if let Some(crop_borders) = self.crop_borders {
+            settings.crop_borders = crop_borders;
+        }
+        if let Some(crop_tolerance) = self.crop_tolerance {
+            settings.crop_tolerance = crop_tolerance;
+        }
+        if let Some(split_spreads) = self.split_spreads {
+            settings.split_spreads = split_spreads;
+        }
+        if let Some(spread_threshold) = self.spread_threshold {
+            settings.spread_threshold = spread_threshold;
+        }
+        if let Some(spread_offset) = self.spread_offset {
+            settings.spread_offset = spread_offset;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CropBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Scroll position in long-strip mode, in pixels at the given strip width.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct StripPosition {
+    pub offset: f64,
+    pub width: u32,
+}
+
+/// Crop boxes detected for a comic, keyed by page index. Pages without a border
+/// are stored as `None`. The boxes are only valid for the tolerance they were
+/// detected with.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct CropBoxCache {
+    pub tolerance: u8,
+    pub boxes: HashMap<usize, Option<CropBox>>,
+}
@@ -1,243 +1,1196 @@
-use crate::cbz_viewer::{CbzViewer, ComicInfo};
-use crate::config_manager::{Config, ConfigManager};
+use crate::bookmark_manager::{Bookmark, BookmarkManager, LibraryBookmark};
+use crate::cbz_viewer::{CbzViewer, ComicInfo, PageDimensions};
+use crate::comic_settings::{ComicSettings, ReadingDirection, StripPosition, ViewPreferences};
+use crate::config_manager::{Config, ConfigFieldError, CONFIG_CHANGED_EVENT};
+use crate::converter::Converter;
+use crate::duplicate_finder::{DuplicateFinder, DuplicateGroup};
 use crate::errors::AppError;
-use crate::file_manager::FileManager;
+use crate::exporter::{DocumentFormat, Exporter, ExtractOptions};
+use crate::file_manager::STRIP_POSITION_FILE;
+use crate::history_manager::{ExportFormat, ReadingSession, ReadingStats};
+use crate::image_processor::FitMode;
+use crate::importer::{ImportReport, Importer};
+use crate::integrity_checker::{IntegrityChecker, IntegrityReport, RepairReport};
+use crate::jobs::{Job, JobItem, JobKind, JobManager};
+use crate::library::{Library, WorkPool};
+use crate::optimizer::{OptimizeOptions, OptimizeReport, Optimizer};
+use crate::page_editor::PageEditor;
+use crate::page_layout::{
+    PageLayout, SpreadPlan, StripLayout, VirtualPage, DEFAULT_STRIP_SEGMENT_HEIGHT,
+};
+use crate::page_renderer::{PageRenderer, PageRequest};
+use crate::preferences_manager::PreferencesManager;
+use crate::progress_manager::{ContinueReadingEntry, ProgressManager, ReadStatus, ReadingState};
+use crate::volume_builder::{MergeOptions, VolumeBuilder};
 use base64::engine::general_purpose;
 use base64::Engine;
 use log::{error, info, warn};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
-use tauri::command;
+use tauri::{command, Emitter, State};
 
 #[command]
-pub async fn list_files(app_handle: tauri::AppHandle) -> Result<Vec<String>, AppError> {
+pub async fn list_files(
+    app_handle: tauri::AppHandle,
+    status: Option<ReadStatus>,
+    series: Option<String>,
+) -> Result<Vec<String>, AppError> {
     info!("Listing files...");
 
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General {
-        message: format!("Error starting FileManager {}", e),
-    })?;
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let files = match &series {
+            Some(series) => library.series_files(series),
+            None => library.list_files(),
+        };
 
-    match fm.list_files() {
-        Ok(files) => {
-            info!("Listed {} files", files.len());
-            Ok(files)
-        }
-        Err(e) => {
-            error!("Error listing files: {}", e);
-            Err(AppError::General {
-                message: format!("Error listing files: {}", e),
+        let files: Vec<String> = files
+            .into_iter()
+            .filter(|file| {
+                status.is_none_or(|status| {
+                    ProgressManager::get_state(&library.files, file)
+                        .is_ok_and(|state| state.status == status)
+                })
             })
-        }
-    }
+            .collect();
+
+        info!("Listed {} files", files.len());
+        Ok(files)
+    })
+    .await
 }
 
+/// Whether the library is still being indexed after startup. Listings are
+/// incomplete until the `library-loaded` event.
 #[command]
-pub fn add_file(app_handle: tauri::AppHandle, source_path: String) -> Result<(), AppError> {
-    info!("Adding file: {}", source_path);
-
-    if !Path::new(&source_path).exists() {
-        warn!("File does not exist: {}", source_path);
-        return Err(AppError::FileNotFound { path: source_path });
-    }
-
-    let source_path_lower = source_path.to_lowercase();
-    if !(source_path_lower.ends_with(".cbz")
-        || source_path_lower.ends_with(".cbr")
-        || source_path_lower.ends_with(".zip")
-        || source_path_lower.ends_with(".rar"))
-    {
-        warn!("Unsupported file format: {}", source_path);
-        return Err(AppError::General {
-            message: format!("Unsupported file format: {}", source_path),
-        });
-    }
+pub fn is_library_loading(library: State<'_, Library>) -> bool {
+    library.is_loading()
+}
 
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General {
-        message: format!("Error starting FileManager: {}", e),
-    })?;
+#[command]
+pub async fn add_file(app_handle: tauri::AppHandle, source_path: String) -> Result<(), AppError> {
+    info!("Adding file: {}", source_path);
 
-    match fm.add_file(&source_path) {
-        Ok(_) => {
-            info!("File added successfully");
-            Ok(())
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        if !Path::new(&source_path).exists() {
+            warn!("File does not exist: {}", source_path);
+            return Err(AppError::FileNotFound { path: source_path });
         }
-        Err(e) => {
-            error!("Error adding file: {}", e);
-            Err(AppError::General {
-                message: format!("Error adding file: {}", e),
-            })
+
+        // Archives the library cannot hold as-is are converted to CBZ
+        match Importer::import(library, &source_path) {
+            Ok(_) => {
+                info!("File added successfully");
+                Ok(())
+            }
+            Err(e) => {
+                error!("Error adding file: {}", e);
+                Err(e)
+            }
         }
-    }
+    })
+    .await
 }
 
 #[command]
-pub fn load_config(app_handle: tauri::AppHandle) -> Result<Config, AppError> {
+pub fn load_config(library: State<'_, Library>) -> Config {
     info!("Loading configuration...");
 
-    let cm = ConfigManager::new(&app_handle).map_err(|e| AppError::General { message: e })?;
-    cm.load_config()
-        .map_err(|e| AppError::General { message: e })
+    library.config()
 }
 
 #[command]
-pub fn save_config(app_handle: tauri::AppHandle, config: Config) -> Result<(), AppError> {
+pub fn validate_config(config: Config) -> Vec<ConfigFieldError> {
+    config.validate()
+}
+
+#[command]
+pub fn save_config(
+    app_handle: tauri::AppHandle,
+    library: State<'_, Library>,
+    config: Config,
+) -> Result<(), AppError> {
     info!("Saving configuration...");
-    let cm = ConfigManager::new(&app_handle).map_err(|e| AppError::General { message: e })?;
-    cm.save_config(&config)
-        .map_err(|e| AppError::General { message: e })
+
+    if let Err(e) = library.set_config(config.clone()) {
+        warn!("Rejected config: {}", e);
+        return Err(e);
+    }
+
+    app_handle.emit(CONFIG_CHANGED_EVENT, &config)?;
+    Ok(())
 }
 
 #[command]
-pub fn read_comic_info(
+pub async fn read_comic_info(
     app_handle: tauri::AppHandle,
     cbz_path: String,
 ) -> Result<ComicInfo, AppError> {
     info!("Reading comic info from: {}", cbz_path);
 
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General { message: e })?;
-    let full_path = fm
-        .get_full_path(&cbz_path)
-        .map_err(|e| AppError::General { message: e })?
-        .to_str()
-        .ok_or_else(|| AppError::General {
-            message: format!("Invalid path for {}", cbz_path),
-        })?
-        .to_string();
-    CbzViewer::read_comic_info(&full_path).map_err(|e| AppError::General { message: e })
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        let full_path = fm.get_full_path(&cbz_path)?;
+        CbzViewer::read_comic_info(&full_path.to_string_lossy())
+    })
+    .await
 }
 
 #[command]
-pub fn get_metadata(app_handle: tauri::AppHandle, cbz_path: String) -> Result<ComicInfo, AppError> {
+pub async fn get_metadata(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<ComicInfo, AppError> {
     info!("Getting metadata for: {}", cbz_path);
 
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General { message: e })?;
-
-    let file_stem = std::path::Path::new(&cbz_path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| AppError::General {
-            message: format!("Invalid file name: {}", cbz_path),
-        })?;
-
-    let metadata_file = fm.directory.join(file_stem).join("metadata.json");
-    let comic_info = if metadata_file.exists() {
-        let metadata_data =
-            std::fs::read_to_string(&metadata_file).map_err(|e| AppError::General {
-                message: format!("Failed to read metadata: {}", e),
-            })?;
-        serde_json::from_str::<ComicInfo>(&metadata_data).map_err(|e| AppError::General {
-            message: format!("Failed to parse metadata: {}", e),
-        })?
-    } else {
-        let full_path = fm
-            .get_full_path(&cbz_path)
-            .map_err(|e| AppError::General { message: e })?;
-        let path_str = full_path.to_str().ok_or_else(|| AppError::General {
-            message: format!("Invalid path for {}", cbz_path),
-        })?;
-        CbzViewer::read_comic_info(path_str).map_err(|e| AppError::General { message: e })?
-    };
-
-    Ok(comic_info)
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        fm.get_metadata(&cbz_path)
+    })
+    .await
 }
 
 #[command]
-pub fn get_cover_image(
+pub async fn get_cover_image(
     app_handle: tauri::AppHandle,
     cbz_path: String,
 ) -> Result<Option<String>, AppError> {
     info!("Getting cover image for: {}", cbz_path);
 
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General { message: e })?;
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
 
-    let file_stem = std::path::Path::new(&cbz_path)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| AppError::General {
-            message: format!("Invalid file name: {}", cbz_path),
-        })?;
+        let comic_folder = fm.get_comic_folder(&cbz_path)?;
 
-    let cover_extensions = vec!["jpg", "jpeg", "png", "webp"];
+        let cover_extensions = vec!["jpg", "jpeg", "png", "webp"];
 
-    for ext in &cover_extensions {
-        let cover_path = fm.directory.join(file_stem).join(format!("cover.{}", ext));
-        if cover_path.exists() {
-            let image_data = fs::read(&cover_path).map_err(|e| AppError::General {
-                message: format!("Failed to read cover image: {}", e),
-            })?;
+        for ext in &cover_extensions {
+            let cover_path = comic_folder.join(format!("cover.{}", ext));
+            if cover_path.exists() {
+                let image_data = fs::read(&cover_path).map_err(|e| AppError::fs(&cover_path, e))?;
 
-            let base64_image = general_purpose::STANDARD.encode(&image_data);
+                let base64_image = general_purpose::STANDARD.encode(&image_data);
 
-            let mime_type = match ext.as_ref() {
-                "png" => "image/png",
-                "webp" => "image/webp",
-                _ => "image/jpeg",
-            };
+                let mime_type = match *ext {
+                    "png" => "image/png",
+                    "webp" => "image/webp",
+                    _ => "image/jpeg",
+                };
 
-            return Ok(Some(format!("data:{};base64,{}", mime_type, base64_image)));
+                return Ok(Some(format!("data:{};base64,{}", mime_type, base64_image)));
+            }
         }
-    }
 
-    Ok(None)
+        Ok(None)
+    })
+    .await
 }
 
 #[command]
-pub fn load_image_by_index(
+pub async fn load_image_by_index(
     app_handle: tauri::AppHandle,
     cbz_path: String,
     image_index: usize,
+    width: Option<u32>,
+    height: Option<u32>,
+    fit_mode: Option<FitMode>,
+    crop: Option<bool>,
 ) -> Result<String, AppError> {
     info!("Loading image index {} from: {}", image_index, cbz_path);
 
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General { message: e })?;
-    let full_path = fm
-        .get_full_path(&cbz_path)
-        .map_err(|e| AppError::General { message: e })?;
-    let path_str = full_path.to_str().ok_or_else(|| AppError::General {
-        message: format!("Invalid path for {}", cbz_path),
-    })?;
-    CbzViewer::load_image_by_index(path_str, image_index)
-        .map_err(|e| AppError::General { message: e })
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        let cache = &library.page_cache;
+        let settings = library.comic_settings(&cbz_path)?;
+
+        let (archive_index, half) = PageLayout::resolve(fm, &cbz_path, &settings, image_index)?;
+
+        let request = PageRequest {
+            width,
+            height,
+            fit_mode: fit_mode.unwrap_or(settings.fit_mode),
+            crop: crop.unwrap_or(settings.crop_borders),
+            crop_tolerance: settings.crop_tolerance,
+            half,
+        };
+
+        PageRenderer::render(fm, cache, &cbz_path, archive_index, &request)
+    })
+    .await
 }
 
 #[command]
-pub fn get_page_count(app_handle: tauri::AppHandle, cbz_path: String) -> Result<usize, AppError> {
+pub async fn get_page_count(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<usize, AppError> {
     info!("Getting page count for: {}", cbz_path);
 
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General { message: e })?;
-    let full_path = fm
-        .get_full_path(&cbz_path)
-        .map_err(|e| AppError::General { message: e })?;
-    let path_str = full_path.to_str().ok_or_else(|| AppError::General {
-        message: format!("Invalid path for {}", cbz_path),
-    })?;
-    let image_list =
-        CbzViewer::get_image_list(path_str).map_err(|e| AppError::General { message: e })?;
-    Ok(image_list.len())
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        let settings = library.comic_settings(&cbz_path)?;
+
+        PageLayout::page_count(fm, &cbz_path, &settings)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_image_list(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<Vec<VirtualPage>, AppError> {
+    info!("Getting image list for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        let settings = library.comic_settings(&cbz_path)?;
+
+        PageLayout::virtual_pages(fm, &cbz_path, &settings)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_spread_plan(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    offset: Option<usize>,
+    direction: Option<ReadingDirection>,
+) -> Result<SpreadPlan, AppError> {
+    info!("Getting spread plan for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        let mut settings = library.comic_settings(&cbz_path)?;
+
+        if let Some(direction) = direction {
+            settings.reading_direction = direction;
+        }
+        let offset = offset.unwrap_or(settings.spread_offset);
+
+        PageLayout::spread_plan(fm, &cbz_path, &settings, offset)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_page_dimensions(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<Vec<PageDimensions>, AppError> {
+    info!("Getting page dimensions for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        PageLayout::page_table(fm, &cbz_path).map(|table| table.pages)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_strip_layout(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    width: u32,
+    stitch: Option<bool>,
+    max_segment_height: Option<u32>,
+) -> Result<StripLayout, AppError> {
+    info!("Getting strip layout for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        PageLayout::strip_layout(
+            fm,
+            &cbz_path,
+            width,
+            stitch.unwrap_or(false),
+            max_segment_height.unwrap_or(DEFAULT_STRIP_SEGMENT_HEIGHT),
+        )
+    })
+    .await
+}
+
+#[command]
+pub async fn load_strip_segment(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    segment_index: usize,
+    width: u32,
+    stitch: Option<bool>,
+    max_segment_height: Option<u32>,
+) -> Result<String, AppError> {
+    info!("Loading strip segment {} from: {}", segment_index, cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        let cache = &library.page_cache;
+        let layout = PageLayout::strip_layout(
+            fm,
+            &cbz_path,
+            width,
+            stitch.unwrap_or(false),
+            max_segment_height.unwrap_or(DEFAULT_STRIP_SEGMENT_HEIGHT),
+        )?;
+
+        let segment = layout.segments.get(segment_index).ok_or_else(|| {
+            AppError::invalid_input(format!("Strip segment {} out of range", segment_index))
+        })?;
+
+        PageRenderer::render_strip_segment(fm, cache, &cbz_path, segment, width)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_strip_position(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<StripPosition, AppError> {
+    info!("Getting strip position for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        fm.read_sidecar(&cbz_path, STRIP_POSITION_FILE)
+    })
+    .await
+}
+
+#[command]
+pub async fn save_strip_position(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    position: StripPosition,
+) -> Result<(), AppError> {
+    info!("Saving strip position for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        fm.write_sidecar(&cbz_path, STRIP_POSITION_FILE, &position)
+    })
+    .await
 }
 
 #[command]
-pub fn delete_file(app_handle: tauri::AppHandle, cbz_path: String) -> Result<(), AppError> {
+pub async fn delete_file(app_handle: tauri::AppHandle, cbz_path: String) -> Result<(), AppError> {
     info!("Deleting file: {}", cbz_path);
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General { message: e })?;
-    fm.delete_file(&cbz_path)
-        .map_err(|e| AppError::General { message: e })
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        library.delete_file(&cbz_path)
+    })
+    .await
 }
 
 #[command]
-pub fn edit_metadata_file(
+pub async fn edit_metadata_file(
     app_handle: tauri::AppHandle,
     cbz_path: String,
     comic_info: ComicInfo,
 ) -> Result<(), AppError> {
     info!("Editing metadata for: {}", cbz_path);
 
-    let fm = FileManager::new(&app_handle).map_err(|e| AppError::General {
-        message: format!("Error starting FileManager: {}", e),
-    })?;
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        library.edit_metadata(&cbz_path, &comic_info)
+    })
+    .await
+}
+
+#[command]
+pub async fn resolve_comic_settings(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<ComicSettings, AppError> {
+    info!("Resolving settings for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        library.comic_settings(&cbz_path)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_comic_preferences(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<ViewPreferences, AppError> {
+    info!("Getting preferences for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        PreferencesManager::get_comic(fm, &cbz_path)
+    })
+    .await
+}
+
+#[command]
+pub async fn save_comic_preferences(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    preferences: ViewPreferences,
+) -> Result<(), AppError> {
+    info!("Saving preferences for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        PreferencesManager::save_comic(fm, &cbz_path, &preferences)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_series_preferences(
+    app_handle: tauri::AppHandle,
+    series: String,
+) -> Result<ViewPreferences, AppError> {
+    info!("Getting preferences for series: {}", series);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let pm = &library.preferences;
+        pm.get_series(&series)
+    })
+    .await
+}
+
+#[command]
+pub async fn save_series_preferences(
+    app_handle: tauri::AppHandle,
+    series: String,
+    preferences: ViewPreferences,
+) -> Result<(), AppError> {
+    info!("Saving preferences for series: {}", series);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let pm = &library.preferences;
+        pm.save_series(&series, &preferences)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_reading_state(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<ReadingState, AppError> {
+    info!("Getting reading state for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        ProgressManager::get_state(fm, &cbz_path)
+    })
+    .await
+}
+
+#[command]
+pub async fn update_reading_progress(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    page: usize,
+    page_count: Option<usize>,
+) -> Result<ReadingState, AppError> {
+    info!(
+        "Updating reading progress for: {} (page {})",
+        cbz_path, page
+    );
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+
+        let page_count = match page_count {
+            Some(count) => count,
+            None => {
+                let settings = library.comic_settings(&cbz_path)?;
+                PageLayout::page_count(fm, &cbz_path, &settings)?
+            }
+        };
+
+        ProgressManager::update_progress(fm, &cbz_path, page, page_count)
+    })
+    .await
+}
 
-    fm.edit_metadata_file(&cbz_path, &comic_info)
-        .map_err(|e| AppError::General {
-            message: format!("Error editing metadata file: {}", e),
+#[command]
+pub async fn get_continue_reading(
+    app_handle: tauri::AppHandle,
+    limit: Option<usize>,
+) -> Result<Vec<ContinueReadingEntry>, AppError> {
+    info!("Getting continue reading list...");
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let mut entries = ProgressManager::continue_reading(library)?;
+
+        if let Some(limit) = limit {
+            entries.truncate(limit);
+        }
+
+        Ok(entries)
+    })
+    .await
+}
+
+#[command]
+pub async fn mark_comics(
+    app_handle: tauri::AppHandle,
+    cbz_paths: Vec<String>,
+    status: ReadStatus,
+) -> Result<(), AppError> {
+    info!("Marking {} comics as {:?}", cbz_paths.len(), status);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let fm = &library.files;
+        let failed = ProgressManager::set_status_bulk(fm, &cbz_paths, status);
+
+        if failed.is_empty() {
+            return Ok(());
+        }
+
+        for failure in &failed {
+            error!("Error marking {}: {}", failure.path, failure.message);
+        }
+        Err(AppError::PartialFailure {
+            total: cbz_paths.len(),
+            failures: failed,
         })
+    })
+    .await
+}
+
+#[command]
+pub async fn mark_series(
+    app_handle: tauri::AppHandle,
+    series: String,
+    status: ReadStatus,
+) -> Result<usize, AppError> {
+    info!("Marking series {} as {:?}", series, status);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let files = library.series_files(&series);
+
+        let failed = ProgressManager::set_status_bulk(&library.files, &files, status);
+        for failure in &failed {
+            error!("Error marking {}: {}", failure.path, failure.message);
+        }
+
+        Ok(files.len() - failed.len())
+    })
+    .await
+}
+
+#[command]
+pub async fn list_bookmarks(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<Vec<Bookmark>, AppError> {
+    info!("Listing bookmarks for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        BookmarkManager::list(fm, &cbz_path)
+    })
+    .await
+}
+
+#[command]
+pub async fn add_bookmark(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    page: usize,
+    label: Option<String>,
+    note: Option<String>,
+) -> Result<Bookmark, AppError> {
+    info!("Adding bookmark to {} at page {}", cbz_path, page);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        BookmarkManager::add(fm, &cbz_path, page, label, note)
+    })
+    .await
+}
+
+#[command]
+pub async fn edit_bookmark(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    id: String,
+    page: Option<usize>,
+    label: Option<String>,
+    note: Option<String>,
+) -> Result<Bookmark, AppError> {
+    info!("Editing bookmark {} of {}", id, cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        BookmarkManager::edit(fm, &cbz_path, &id, page, label, note)
+    })
+    .await
+}
+
+#[command]
+pub async fn delete_bookmark(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    id: String,
+) -> Result<(), AppError> {
+    info!("Deleting bookmark {} of {}", id, cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        BookmarkManager::delete(fm, &cbz_path, &id)
+    })
+    .await
+}
+
+#[command]
+pub async fn list_all_bookmarks(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<LibraryBookmark>, AppError> {
+    info!("Listing all bookmarks...");
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        BookmarkManager::list_all(library)
+    })
+    .await
+}
+
+#[command]
+pub async fn start_reading_session(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<ReadingSession, AppError> {
+    info!("Starting reading session for: {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let hm = &library.history;
+        let series = library
+            .metadata(&cbz_path)
+            .map(|info| info.series)
+            .unwrap_or_default();
+
+        hm.start_session(&cbz_path, &series)
+    })
+    .await
+}
+
+#[command]
+pub async fn record_page_view(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+    page: usize,
+    seconds: f64,
+) -> Result<(), AppError> {
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let hm = &library.history;
+        hm.record_page_view(&session_id, page, seconds)
+    })
+    .await
+}
+
+#[command]
+pub async fn end_reading_session(
+    app_handle: tauri::AppHandle,
+    session_id: String,
+) -> Result<ReadingSession, AppError> {
+    info!("Ending reading session: {}", session_id);
+
+    Library::run(&app_handle, WorkPool::Pages, move |library| {
+        let fm = &library.files;
+        let hm = &library.history;
+
+        let session = hm.session(&session_id)?;
+
+        let completed = ProgressManager::get_state(fm, &session.file_name)
+            .ok()
+            .and_then(|state| state.finished_at)
+            .is_some_and(|finished| finished >= session.started_at);
+
+        hm.end_session(&session_id, completed)
+    })
+    .await
+}
+
+#[command]
+pub async fn get_reading_stats(app_handle: tauri::AppHandle) -> Result<ReadingStats, AppError> {
+    info!("Getting reading statistics...");
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let hm = &library.history;
+        hm.stats()
+    })
+    .await
+}
+
+#[command]
+pub async fn export_reading_history(
+    app_handle: tauri::AppHandle,
+    destination: String,
+    format: ExportFormat,
+) -> Result<usize, AppError> {
+    info!("Exporting reading history to: {}", destination);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let hm = &library.history;
+        hm.export(&destination, format)
+    })
+    .await
+}
+
+/// Imports files and folders; folders are searched recursively for comics.
+#[command]
+pub async fn start_import(
+    app_handle: tauri::AppHandle,
+    source_paths: Vec<String>,
+) -> Result<Job, AppError> {
+    info!("Starting import of {} paths", source_paths.len());
+
+    let items = Library::run(&app_handle, WorkPool::Library, move |_| {
+        Ok(Importer::collect(&source_paths))
+    })
+    .await?;
+
+    Ok(JobManager::start(
+        &app_handle,
+        JobKind::Import,
+        items,
+        |library, path| {
+            let (file_name, similar) = Importer::import(library, path)?;
+            if similar.is_empty() {
+                return Ok(None);
+            }
+
+            Ok(Some(format!(
+                "Imported as {}, which shares most pages with {}",
+                file_name,
+                similar.join(", ")
+            )))
+        },
+    ))
+}
+
+#[command]
+pub fn get_import_report(
+    jobs: State<'_, JobManager>,
+    id: String,
+) -> Result<ImportReport, AppError> {
+    let job = jobs.get(&id)?;
+    if job.kind != JobKind::Import {
+        return Err(AppError::invalid_input(format!(
+            "Not an import job: {}",
+            id
+        )));
+    }
+
+    Ok(Importer::report(&job))
+}
+
+#[command]
+pub async fn start_library_scan(app_handle: tauri::AppHandle) -> Result<Job, AppError> {
+    info!("Starting library scan...");
+
+    let items = Library::run(&app_handle, WorkPool::Library, |library| {
+        library.rebuild_index()?;
+        Ok(library_items(library, library.list_files()))
+    })
+    .await?;
+
+    Ok(JobManager::start(
+        &app_handle,
+        JobKind::Scan,
+        items,
+        |library, file_name| {
+            PageLayout::page_table(&library.files, file_name)?;
+            library.refresh(file_name);
+            library.fingerprint(file_name)?;
+            Ok(None)
+        },
+    ))
+}
+
+#[command]
+pub async fn rebuild_thumbnails(
+    app_handle: tauri::AppHandle,
+    cbz_paths: Option<Vec<String>>,
+) -> Result<Job, AppError> {
+    info!("Starting thumbnail rebuild...");
+
+    let items = Library::run(&app_handle, WorkPool::Library, |library| {
+        let files = cbz_paths.unwrap_or_else(|| library.list_files());
+        Ok(library_items(library, files))
+    })
+    .await?;
+
+    Ok(JobManager::start(
+        &app_handle,
+        JobKind::RebuildThumbnails,
+        items,
+        |library, file_name| library.files.rebuild_cover(file_name).map(|_| None),
+    ))
+}
+
+/// Lists groups of comics that are copies, repacks or near duplicates of each
+/// other. Comics not hashed yet are hashed first.
+#[command]
+pub async fn find_duplicates(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<DuplicateGroup>, AppError> {
+    info!("Looking for duplicates...");
+
+    Library::run(&app_handle, WorkPool::Library, |library| {
+        let groups = DuplicateFinder::find(&library.fingerprints());
+        info!("Found {} duplicate groups", groups.len());
+        Ok(groups)
+    })
+    .await
+}
+
+#[command]
+pub async fn verify_archive(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<IntegrityReport, AppError> {
+    info!("Verifying {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        IntegrityChecker::check(&library.files, &cbz_path)
+    })
+    .await
+}
+
+/// Verifies every comic, or the given ones. Unhealthy comics are done with a
+/// note describing what is wrong.
+#[command]
+pub async fn start_integrity_scan(
+    app_handle: tauri::AppHandle,
+    cbz_paths: Option<Vec<String>>,
+) -> Result<Job, AppError> {
+    info!("Starting integrity scan...");
+
+    let items = Library::run(&app_handle, WorkPool::Library, |library| {
+        let files = cbz_paths.unwrap_or_else(|| library.list_files());
+        Ok(library_items(library, files))
+    })
+    .await?;
+
+    Ok(JobManager::start(
+        &app_handle,
+        JobKind::Verify,
+        items,
+        |library, file_name| {
+            let report = IntegrityChecker::check(&library.files, file_name)?;
+            Ok((!report.is_healthy()).then(|| report.summary()))
+        },
+    ))
+}
+
+/// Comics whose last integrity check found problems, keyed by file name.
+/// Comics changed since their last check are left out.
+#[command]
+pub async fn list_unhealthy_comics(
+    app_handle: tauri::AppHandle,
+) -> Result<BTreeMap<String, IntegrityReport>, AppError> {
+    Library::run(&app_handle, WorkPool::Library, |library| {
+        Ok(library
+            .list_files()
+            .into_iter()
+            .filter_map(|file_name| {
+                let report = IntegrityChecker::stored(&library.files, &file_name)?;
+                (!report.is_healthy()).then_some((file_name, report))
+            })
+            .collect())
+    })
+    .await
+}
+
+#[command]
+pub async fn repair_archive(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<RepairReport, AppError> {
+    info!("Repairing {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let _lock = library.lock_file(&cbz_path);
+        let repair = IntegrityChecker::repair(&library.files, &cbz_path)?;
+        library.refresh(&cbz_path);
+        Ok(repair)
+    })
+    .await
+}
+
+/// Converts comics to CBZ. Each item is either a library file name, converted
+/// in place, or a file or folder from outside the library, converted and
+/// imported.
+#[command]
+pub async fn start_conversion(
+    app_handle: tauri::AppHandle,
+    sources: Vec<String>,
+    replace_original: bool,
+) -> Result<Job, AppError> {
+    info!("Starting conversion of {} comics", sources.len());
+
+    let items = Library::run(&app_handle, WorkPool::Library, move |library| {
+        Ok(sources
+            .into_iter()
+            .map(|source| {
+                if library.contains(&source) {
+                    library_items(library, vec![source]).remove(0)
+                } else {
+                    JobItem::file(&source)
+                }
+            })
+            .collect())
+    })
+    .await?;
+
+    Ok(JobManager::start(
+        &app_handle,
+        JobKind::Convert,
+        items,
+        move |library, source| {
+            if !library.contains(source) {
+                let (file_name, _) =
+                    Converter::import(library, Path::new(source), replace_original)?;
+                return Ok(Some(format!("Imported as {}", file_name)));
+            }
+
+            let _lock = library.lock_file(source);
+            let metadata = library.metadata(source);
+            let file_name = Converter::convert_library_comic(
+                &library.files,
+                source,
+                metadata.as_ref(),
+                replace_original,
+            )?;
+            library.renamed(source, &file_name);
+            Ok(None)
+        },
+    ))
+}
+
+/// Re-encodes the pages of each comic into an optimised copy. The originals
+/// stay in place until `confirm_optimize` is called for them.
+#[command]
+pub async fn start_optimize(
+    app_handle: tauri::AppHandle,
+    cbz_paths: Vec<String>,
+    options: OptimizeOptions,
+) -> Result<Job, AppError> {
+    info!("Starting optimisation of {} comics", cbz_paths.len());
+
+    let items = Library::run(&app_handle, WorkPool::Library, |library| {
+        Ok(library_items(library, cbz_paths))
+    })
+    .await?;
+
+    Ok(JobManager::start(
+        &app_handle,
+        JobKind::Optimize,
+        items,
+        move |library, file_name| {
+            let report = Optimizer::optimize(&library.files, file_name, &options)?;
+            Ok(Some(report.summary()))
+        },
+    ))
+}
+
+#[command]
+pub async fn get_pending_optimize(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<Option<OptimizeReport>, AppError> {
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        Optimizer::pending(&library.files, &cbz_path)
+    })
+    .await
+}
+
+#[command]
+pub async fn confirm_optimize(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<OptimizeReport, AppError> {
+    info!("Keeping optimised archive of {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let _lock = library.lock_file(&cbz_path);
+        let report = Optimizer::confirm(&library.files, &cbz_path)?;
+        library.refresh(&cbz_path);
+        Ok(report)
+    })
+    .await
+}
+
+#[command]
+pub async fn discard_optimize(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+) -> Result<(), AppError> {
+    info!("Discarding optimised archive of {}", cbz_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        Optimizer::discard(&library.files, &cbz_path)
+    })
+    .await
+}
+
+/// Removes pages from a comic. Returns the new page count.
+#[command]
+pub async fn delete_pages(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    page_indexes: Vec<usize>,
+) -> Result<usize, AppError> {
+    info!("Deleting {} pages from {}", page_indexes.len(), cbz_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let _lock = library.lock_file(&cbz_path);
+        let page_count = PageEditor::delete_pages(&library.files, &cbz_path, &page_indexes)?;
+        library.refresh(&cbz_path);
+        Ok(page_count)
+    })
+    .await
+}
+
+#[command]
+pub async fn move_page(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    from: usize,
+    to: usize,
+) -> Result<usize, AppError> {
+    info!("Moving page {} to {} in {}", from, to, cbz_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let _lock = library.lock_file(&cbz_path);
+        let page_count = PageEditor::move_page(&library.files, &cbz_path, from, to)?;
+        library.refresh(&cbz_path);
+        Ok(page_count)
+    })
+    .await
+}
+
+#[command]
+pub async fn insert_pages(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    position: usize,
+    image_paths: Vec<String>,
+) -> Result<usize, AppError> {
+    info!("Inserting {} pages into {}", image_paths.len(), cbz_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let _lock = library.lock_file(&cbz_path);
+        let page_count =
+            PageEditor::insert_pages(&library.files, &cbz_path, position, &image_paths)?;
+        library.refresh(&cbz_path);
+        Ok(page_count)
+    })
+    .await
+}
+
+#[command]
+pub async fn set_cover_page(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    page_index: usize,
+) -> Result<usize, AppError> {
+    info!("Setting page {} as the cover of {}", page_index, cbz_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        let _lock = library.lock_file(&cbz_path);
+        let page_count = PageEditor::set_cover(&library.files, &cbz_path, page_index)?;
+        library.refresh(&cbz_path);
+        Ok(page_count)
+    })
+    .await
+}
+
+#[command]
+pub async fn merge_comics(
+    app_handle: tauri::AppHandle,
+    cbz_paths: Vec<String>,
+    options: MergeOptions,
+) -> Result<String, AppError> {
+    info!("Merging {} comics", cbz_paths.len());
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        VolumeBuilder::merge(library, &cbz_paths, &options)
+    })
+    .await
+}
+
+#[command]
+pub async fn split_comic(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    page_indexes: Vec<usize>,
+) -> Result<Vec<String>, AppError> {
+    info!("Splitting {} before pages {:?}", cbz_path, page_indexes);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        VolumeBuilder::split(library, &cbz_path, &page_indexes)
+    })
+    .await
+}
+
+#[command]
+pub async fn export_comic(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    format: DocumentFormat,
+    output_path: String,
+) -> Result<usize, AppError> {
+    info!("Exporting {} as {:?} to {}", cbz_path, format, output_path);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        Exporter::export(library, &cbz_path, format, &output_path)
+    })
+    .await
+}
+
+#[command]
+pub async fn extract_pages(
+    app_handle: tauri::AppHandle,
+    cbz_path: String,
+    output_dir: String,
+    options: ExtractOptions,
+) -> Result<Vec<String>, AppError> {
+    info!("Extracting pages of {} to {}", cbz_path, output_dir);
+
+    Library::run(&app_handle, WorkPool::Library, move |library| {
+        Exporter::extract_pages(library, &cbz_path, &output_dir, &options)
+    })
+    .await
+}
+
+fn library_items(library: &Library, file_names: Vec<String>) -> Vec<JobItem> {
+    file_names
+        .into_iter()
+        .map(|file_name| {
+            let bytes = library
+                .files
+                .get_full_path(&file_name)
+                .ok()
+                .and_then(|path| fs::metadata(path).ok())
+                .map_or(0, |metadata| metadata.len());
+            JobItem {
+                name: file_name,
+                bytes,
+            }
+        })
+        .collect()
+}
+
+#[command]
+pub fn list_jobs(jobs: State<'_, JobManager>) -> Vec<Job> {
+    jobs.list()
+}
+
+#[command]
+pub fn get_job(jobs: State<'_, JobManager>, id: String) -> Result<Job, AppError> {
+    jobs.get(&id)
+}
+
+#[command]
+pub fn cancel_job(jobs: State<'_, JobManager>, id: String) -> Result<(), AppError> {
+    info!("Cancelling job: {}", id);
+    jobs.cancel(&id)
 }
@@ -1,38 +1,356 @@
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use tauri::{AppHandle, Manager};
-#[derive(Serialize, Deserialize)]
-pub struct Config {}
 
-// For future configuration options
+use crate::comic_settings::{ReadingDirection, ViewPreferences};
+use crate::errors::AppError;
+
+pub const CONFIG_VERSION: u64 = 1;
+pub const CONFIG_CHANGED_EVENT: &str = "config-changed";
+pub const MAX_PREFETCH_WINDOW: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Copy imported files into the library and leave the originals in place.
+    #[default]
+    Copy,
+    /// Move imported files into the library.
+    Move,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    System,
+    Light,
+    Dark,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct LibraryConfig {
+    /// Folders scanned for comics in addition to the library itself.
+    pub paths: Vec<String>,
+    pub import_mode: ImportMode,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ReaderConfig {
+    /// View preferences applied to every comic unless its series or the comic overrides them.
+    pub default_view: ViewPreferences,
+    /// Number of pages rendered ahead of the current one.
+    pub prefetch_window: usize,
+}
+
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        Self {
+            default_view: ViewPreferences {
+                reading_direction: Some(ReadingDirection::LeftToRight),
+                ..Default::default()
+            },
+            prefetch_window: 2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Maximum size of the rendered page cache, in megabytes.
+    pub page_cache_mb: i64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { page_cache_mb: 512 }
+    }
+}
+
+impl CacheConfig {
+    pub fn page_cache_bytes(&self) -> u64 {
+        self.page_cache_mb.max(0) as u64 * 1024 * 1024
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    pub version: u64,
+    pub library: LibraryConfig,
+    pub theme: Theme,
+    pub reader: ReaderConfig,
+    pub cache: CacheConfig,
+    /// Maps reader actions to key names, as reported by `KeyboardEvent.key`.
+    pub key_bindings: BTreeMap<String, String>,
+    /// Keys this version does not know about, kept so they survive a save.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let key_bindings = [
+            ("next_page", "ArrowRight"),
+            ("previous_page", "ArrowLeft"),
+            ("toggle_fullscreen", "f"),
+            ("close", "Escape"),
+        ]
+        .into_iter()
+        .map(|(action, key)| (action.to_string(), key.to_string()))
+        .collect();
+
+        Self {
+            version: CONFIG_VERSION,
+            library: LibraryConfig::default(),
+            theme: Theme::default(),
+            reader: ReaderConfig::default(),
+            cache: CacheConfig::default(),
+            key_bindings,
+            extra: Map::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ConfigFieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl Config {
+    /// Checks the values serde cannot, returning one error per invalid field.
+    pub fn validate(&self) -> Vec<ConfigFieldError> {
+        let mut errors = Vec::new();
+        let mut fail = |field: String, message: String| {
+            errors.push(ConfigFieldError { field, message });
+        };
+
+        for (i, path) in self.library.paths.iter().enumerate() {
+            let field = format!("library.paths[{}]", i);
+            if !Path::new(path).exists() {
+                fail(field, format!("Path does not exist: {}", path));
+            } else if !Path::new(path).is_dir() {
+                fail(field, format!("Path is not a directory: {}", path));
+            }
+        }
+
+        if self.cache.page_cache_mb < 0 {
+            fail(
+                "cache.page_cache_mb".to_string(),
+                "Cache size must not be negative".to_string(),
+            );
+        }
+
+        if self.reader.prefetch_window > MAX_PREFETCH_WINDOW {
+            fail(
+                "reader.prefetch_window".to_string(),
+                format!("Prefetch window must be at most {}", MAX_PREFETCH_WINDOW),
+            );
+        }
+
+        let view = &self.reader.default_view;
+        if view.zoom.is_some_and(|zoom| zoom <= 0.0) {
+            fail(
+                "reader.default_view.zoom".to_string(),
+                "Zoom must be greater than zero".to_string(),
+            );
+        }
+        if view.spread_threshold.is_some_and(|t| t <= 0.0) {
+            fail(
+                "reader.default_view.spread_threshold".to_string(),
+                "Spread threshold must be greater than zero".to_string(),
+            );
+        }
+
+        let mut bound_keys: BTreeMap<&str, &str> = BTreeMap::new();
+        for (action, key) in &self.key_bindings {
+            let field = format!("key_bindings.{}", action);
+            if key.trim().is_empty() {
+                fail(field, "Key must not be empty".to_string());
+            } else if let Some(other) = bound_keys.insert(key, action) {
+                fail(
+                    field,
+                    format!("Key '{}' is already bound to {}", key, other),
+                );
+            }
+        }
+
+        errors
+    }
+}
+
+/// One schema change. `up` migrates a config from `version - 1` to `version`
+/// and `down` reverts it.
+struct Migration {
+    version: u64,
+    up: fn(&mut Map<String, Value>),
+    down: fn(&mut Map<String, Value>),
+}
+
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    up: migrate_v1_up,
+    down: migrate_v1_down,
+}];
+
+// Version 0 was unversioned and kept the default view preferences at the top level
+fn migrate_v1_up(config: &mut Map<String, Value>) {
+    if let Some(default_view) = config.remove("default_view") {
+        let reader = config
+            .entry("reader")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Some(reader) = reader.as_object_mut() {
+            reader.insert("default_view".to_string(), default_view);
+        }
+    }
+}
+
+fn migrate_v1_down(config: &mut Map<String, Value>) {
+    let default_view = config
+        .get_mut("reader")
+        .and_then(Value::as_object_mut)
+        .and_then(|reader| reader.remove("default_view"));
+
+    if let Some(default_view) = default_view {
+        config.insert("default_view".to_string(), default_view);
+    }
+}
 
 pub struct ConfigManager {
     pub config_path: PathBuf,
+    /// Schema of the config file on disk. A file from a newer version is
+    /// never written over, since its keys may already be in a layout this
+    /// version does not know.
+    file_version: AtomicU64,
 }
 
 impl ConfigManager {
-    pub fn new(app: &AppHandle) -> Result<Self, String> {
-        let mut config_dir = app.path().app_config_dir().map_err(|e| e.to_string())?;
+    pub fn new(app: &AppHandle) -> Result<Self, AppError> {
+        let mut config_dir = app.path().app_config_dir()?;
 
-        fs::create_dir_all(&config_dir).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&config_dir).map_err(|e| AppError::fs(&config_dir, e))?;
 
         config_dir.push("config.json");
 
         Ok(Self {
             config_path: config_dir,
+            file_version: AtomicU64::new(CONFIG_VERSION),
         })
     }
 
-    pub fn load_config(&self) -> Result<Config, String> {
+    /// Moves a raw config between schema versions, in either direction.
+    pub fn migrate(config: &mut Map<String, Value>, from: u64, to: u64) {
+        if from < to {
+            for migration in MIGRATIONS
+                .iter()
+                .filter(|m| m.version > from && m.version <= to)
+            {
+                (migration.up)(config);
+            }
+        } else {
+            for migration in MIGRATIONS
+                .iter()
+                .rev()
+                .filter(|m| m.version <= from && m.version > to)
+            {
+                (migration.down)(config);
+            }
+        }
+
+        config.insert("version".to_string(), Value::from(to));
+    }
+
+    /// Loads the config, creating a default one on first run and upgrading
+    /// files written by older versions. A file written by a newer version is
+    /// read for the keys this version knows and left as it is.
+    pub fn load_config(&self) -> Result<Config, AppError> {
         let config_file = &self.config_path;
-        let config_data = std::fs::read_to_string(config_file).map_err(|e| e.to_string())?;
-        serde_json::from_str(&config_data).map_err(|e| e.to_string())
+
+        if !config_file.exists() {
+            info!("No config found, creating default config");
+            let config = Config::default();
+            self.save_config(&config)?;
+            return Ok(config);
+        }
+
+        let config_data =
+            std::fs::read_to_string(config_file).map_err(|e| AppError::fs(config_file, e))?;
+        let mut raw = match serde_json::from_str::<Value>(&config_data) {
+            Ok(Value::Object(raw)) => raw,
+            Ok(_) | Err(_) => {
+                warn!("Config file is not a JSON object, replacing it with defaults");
+                return self.reset_config();
+            }
+        };
+
+        let version = raw.get("version").and_then(Value::as_u64).unwrap_or(0);
+
+        if version > CONFIG_VERSION {
+            warn!(
+                "Config was written by a newer version (schema {}), reading known keys only",
+                version
+            );
+            self.file_version.store(version, Ordering::Relaxed);
+        } else if version < CONFIG_VERSION {
+            info!(
+                "Migrating config from schema {} to {}",
+                version, CONFIG_VERSION
+            );
+            Self::migrate(&mut raw, version, CONFIG_VERSION);
+        }
+
+        match serde_json::from_value::<Config>(Value::Object(raw)) {
+            Ok(config) => {
+                if version < CONFIG_VERSION {
+                    self.save_config(&config)?;
+                }
+                Ok(config)
+            }
+            Err(e) if version > CONFIG_VERSION => {
+                warn!("Cannot read newer config ({}), using defaults", e);
+                Ok(Config::default())
+            }
+            Err(e) => {
+                warn!("Invalid config ({}), replacing it with defaults", e);
+                self.reset_config()
+            }
+        }
     }
 
-    pub fn save_config(&self, config: &Config) -> Result<(), String> {
+    /// Keeps a backup of an unreadable config and writes the defaults in its place.
+    fn reset_config(&self) -> Result<Config, AppError> {
+        let backup_path = self.config_path.with_extension("json.bak");
+        fs::copy(&self.config_path, &backup_path).map_err(|e| AppError::fs(&backup_path, e))?;
+
+        let config = Config::default();
+        self.save_config(&config)?;
+        Ok(config)
+    }
+
+    /// Writes the config aside and renames it into place, so a crash halfway
+    /// leaves the previous file rather than one that resets to defaults.
+    pub fn save_config(&self, config: &Config) -> Result<(), AppError> {
+        let file_version = self.file_version.load(Ordering::Relaxed);
+        if file_version > CONFIG_VERSION {
+            return Err(AppError::invalid_input(format!(
+                "The config was saved by a newer version of the app (schema {}) and cannot be changed by this one",
+                file_version
+            )));
+        }
+
         let config_file = &self.config_path;
-        let config_data = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
-        std::fs::write(config_file, config_data).map_err(|e| e.to_string())
+        let temp_file = config_file.with_extension("json.tmp");
+        let config_data = serde_json::to_string_pretty(config)?;
+        fs::write(&temp_file, config_data).map_err(|e| AppError::fs(&temp_file, e))?;
+        fs::rename(&temp_file, config_file).map_err(|e| AppError::fs(config_file, e))
     }
 }
@@ -0,0 +1,580 @@
+use image::{DynamicImage, GrayImage, RgbImage};
+use log::{info, warn};
+use lopdf::{Document, Object};
+use sevenz_rust::{Password, SevenZReader};
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::cbz_viewer::{CbzViewer, ComicInfo, COMIC_INFO_TEMPLATE};
+use crate::errors::AppError;
+use crate::file_manager::{FileManager, UNKNOWN_WRITER};
+use crate::image_processor::ImageProcessor;
+use crate::library::Library;
+use crate::page_editor::PageEditor;
+
+/// Page images picked up from a source. JPEG, PNG and WebP pages are copied as
+/// they are; the rest are converted to PNG so the viewer can show them.
+const SOURCE_IMAGE_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "webp", "gif", "bmp"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SourceFormat {
+    Zip,
+    Rar,
+    SevenZip,
+    Pdf,
+    Folder,
+}
+
+impl SourceFormat {
+    fn detect(source: &Path) -> Result<Self, AppError> {
+        if source.is_dir() {
+            return Ok(Self::Folder);
+        }
+
+        let extension = source
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase)
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "cbz" | "zip" => Ok(Self::Zip),
+            "cbr" | "rar" => Ok(Self::Rar),
+            "cb7" | "7z" => Ok(Self::SevenZip),
+            "pdf" => Ok(Self::Pdf),
+            _ => Err(AppError::unsupported_format(source.display().to_string())),
+        }
+    }
+}
+
+/// A scratch folder for extracted pages and archives on their way into the
+/// library, removed when dropped.
+pub struct StagingDir(PathBuf);
+
+impl StagingDir {
+    pub fn new() -> Result<Self, AppError> {
+        let path = std::env::temp_dir().join(format!("gihon-staging-{}", Uuid::new_v4()));
+        fs::create_dir_all(&path).map_err(|e| AppError::fs(&path, e))?;
+        Ok(Self(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.0) {
+            warn!("Failed to remove {}: {}", self.0.display(), e);
+        }
+    }
+}
+
+/// Joins an archive entry name onto a folder, refusing names that would
+/// escape it.
+fn safe_join(root: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+
+    for component in Path::new(&name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    Some(path)
+}
+
+pub struct Converter;
+
+impl Converter {
+    /// Repacks a comic archive, a PDF or a folder of images into a clean CBZ at
+    /// `destination`. Pages are renamed in natural order. `comic_info` is
+    /// embedded as ComicInfo.xml, merged into the source's own when it has
+    /// one. Returns the source path of every page written, relative to the
+    /// source and in their new order.
+    pub fn convert(
+        source: &Path,
+        destination: &Path,
+        comic_info: Option<&ComicInfo>,
+    ) -> Result<Vec<String>, AppError> {
+        let format = SourceFormat::detect(source)?;
+        info!("Converting {} ({:?})", source.display(), format);
+
+        if format == SourceFormat::Folder {
+            return Self::pack(source, destination, comic_info);
+        }
+
+        let staging = StagingDir::new()?;
+        match format {
+            SourceFormat::Zip => Self::extract_zip(source, &staging.0)?,
+            SourceFormat::Rar => Self::extract_rar(source, &staging.0)?,
+            SourceFormat::SevenZip => Self::extract_7z(source, &staging.0)?,
+            SourceFormat::Pdf => Self::extract_pdf(source, &staging.0)?,
+            SourceFormat::Folder => unreachable!(),
+        }
+
+        Self::pack(&staging.0, destination, comic_info)
+    }
+
+    /// Whether a file is an archive or PDF the library cannot read as it is but
+    /// can import by converting it to CBZ first.
+    pub fn can_convert(path: &Path) -> bool {
+        matches!(
+            SourceFormat::detect(path),
+            Ok(SourceFormat::Rar | SourceFormat::SevenZip | SourceFormat::Pdf)
+        ) && path.is_file()
+    }
+
+    /// Converts a comic in the library to CBZ in place. The converted archive
+    /// keeps the comic's folder, so its settings and progress carry over. The
+    /// original is deleted when `replace_original` is set and kept with a
+    /// `.bak` extension otherwise. Returns the new file name.
+    pub fn convert_library_comic(
+        fm: &FileManager,
+        file_name: &str,
+        comic_info: Option<&ComicInfo>,
+        replace_original: bool,
+    ) -> Result<String, AppError> {
+        let full_path = fm.get_full_path(file_name)?;
+        let folder_path = fm.get_comic_folder(file_name)?;
+        let stem = full_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| AppError::invalid_input(format!("Invalid file name: {}", file_name)))?;
+
+        let new_file_name = format!("{}.cbz", stem);
+        let new_path = folder_path.join(&new_file_name);
+        let temp_path = new_path.with_extension(format!("{}.convert.tmp", Uuid::new_v4()));
+
+        // Comics imported without a ComicInfo.xml have placeholder metadata,
+        // which is not worth writing into the archive
+        let comic_info = comic_info.map(|info| ComicInfo {
+            title: if info.title == stem {
+                String::new()
+            } else {
+                info.title.clone()
+            },
+            writer: if info.writer == UNKNOWN_WRITER {
+                String::new()
+            } else {
+                info.writer.clone()
+            },
+            ..info.clone()
+        });
+
+        let old_pages = CbzViewer::get_image_list(&full_path.to_string_lossy()).ok();
+        let new_pages = match Self::convert(&full_path, &temp_path, comic_info.as_ref()) {
+            Ok(new_pages) => new_pages,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        };
+
+        if replace_original {
+            fs::remove_file(&full_path).map_err(|e| AppError::fs(&full_path, e))?;
+        } else {
+            let extension = full_path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("cbz");
+            let backup_path = full_path.with_extension(format!("{}.bak", extension));
+            fs::rename(&full_path, &backup_path).map_err(|e| AppError::fs(&full_path, e))?;
+        }
+        fs::rename(&temp_path, &new_path).map_err(|e| AppError::fs(&temp_path, e))?;
+
+        // Pages may come out in a different order. Without a readable page
+        // list to match against, everything stored by page index is cleared.
+        let new_indexes: Vec<Option<usize>> = old_pages
+            .unwrap_or_default()
+            .iter()
+            .map(|old| new_pages.iter().position(|new| new == old))
+            .collect();
+        PageEditor::remap_sidecars(fm, &new_file_name, &new_indexes, new_pages.len());
+
+        Ok(new_file_name)
+    }
+
+    /// Converts a file or folder from outside the library and imports the
+    /// result. A source file is deleted afterwards when `replace_original` is
+    /// set; folders are always left alone. Returns the new file name and the
+    /// comics sharing most of its pages.
+    pub fn import(
+        library: &Library,
+        source: &Path,
+        replace_original: bool,
+    ) -> Result<(String, Vec<String>), AppError> {
+        let stem = source
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| {
+                AppError::invalid_input(format!("Invalid file name: {}", source.display()))
+            })?;
+
+        let staging = StagingDir::new()?;
+        let converted = staging.0.join(format!("{}.cbz", stem));
+        Self::convert(source, &converted, None)?;
+
+        let imported = library.add_file(&converted.to_string_lossy())?;
+
+        if replace_original && source.is_file() {
+            fs::remove_file(source).map_err(|e| AppError::fs(source, e))?;
+        }
+
+        Ok(imported)
+    }
+
+    fn extract_zip(source: &Path, staging: &Path) -> Result<(), AppError> {
+        let path = source.to_string_lossy();
+        CbzViewer::open_archive(&path)?
+            .extract(staging)
+            .map_err(|e| AppError::archive_corrupt(path, e))
+    }
+
+    fn extract_7z(source: &Path, staging: &Path) -> Result<(), AppError> {
+        let path = source.display().to_string();
+        let mut reader = SevenZReader::open(source, Password::empty())
+            .map_err(|e| AppError::archive_corrupt(&path, e))?;
+
+        reader
+            .for_each_entries(|entry, data| {
+                if entry.is_directory() {
+                    return Ok(true);
+                }
+
+                let Some(target) = safe_join(staging, entry.name()) else {
+                    warn!("Skipping unsafe entry name: {}", entry.name());
+                    io::copy(data, &mut io::sink())?;
+                    return Ok(true);
+                };
+
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(data, &mut File::create(&target)?)?;
+                Ok(true)
+            })
+            .map_err(|e| AppError::archive_corrupt(&path, e))
+    }
+
+    /// RAR has no pure Rust decoder, so the first extractor found on the
+    /// system is used.
+    fn extract_rar(source: &Path, staging: &Path) -> Result<(), AppError> {
+        let path = source.display().to_string();
+        let extractors: [(&str, Vec<OsString>); 3] = [
+            (
+                "unrar",
+                vec![
+                    "x".into(),
+                    "-y".into(),
+                    "-idq".into(),
+                    source.into(),
+                    staging.join("").into(),
+                ],
+            ),
+            (
+                "7z",
+                vec![
+                    "x".into(),
+                    "-y".into(),
+                    format!("-o{}", staging.display()).into(),
+                    source.into(),
+                ],
+            ),
+            (
+                "bsdtar",
+                vec!["-xf".into(), source.into(), "-C".into(), staging.into()],
+            ),
+        ];
+
+        // An extractor that fails, such as an old unrar without RAR5 support,
+        // leaves the archive to the next one
+        let mut failures = Vec::new();
+        for (extractor, args) in extractors {
+            let output = Command::new(extractor)
+                .args(&args)
+                .stdin(Stdio::null())
+                .output();
+
+            match output {
+                Ok(output) if output.status.success() => return Ok(()),
+                Ok(output) => {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    warn!("{} failed on {}: {}", extractor, path, stderr.trim());
+                    failures.push(format!("{} failed: {}", extractor, stderr.trim()));
+                    Self::clear_folder(staging)?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(AppError::fs(source, e)),
+            }
+        }
+
+        if !failures.is_empty() {
+            return Err(AppError::archive_corrupt(path, failures.join("; ")));
+        }
+        Err(AppError::UnsupportedFormat {
+            path,
+            source: Some("No RAR extractor found; install unrar, 7-Zip or bsdtar".into()),
+        })
+    }
+
+    /// Removes whatever a failed extraction left behind.
+    fn clear_folder(folder: &Path) -> Result<(), AppError> {
+        let entries = fs::read_dir(folder).map_err(|e| AppError::fs(folder, e))?;
+        for entry in entries {
+            let path = entry.map_err(|e| AppError::fs(folder, e))?.path();
+            let removed = if path.is_dir() && !path.is_symlink() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            removed.map_err(|e| AppError::fs(&path, e))?;
+        }
+        Ok(())
+    }
+
+    /// Writes the largest image of every page. Only PDFs made of scanned or
+    /// embedded page images can be converted; pages drawn from text or vector
+    /// graphics are not rendered.
+    fn extract_pdf(source: &Path, staging: &Path) -> Result<(), AppError> {
+        let path = source.display().to_string();
+        let document = Document::load(source).map_err(|e| AppError::archive_corrupt(&path, e))?;
+
+        for (number, page_id) in document.get_pages() {
+            let images = document
+                .get_page_images(page_id)
+                .map_err(|e| AppError::archive_corrupt(&path, e))?;
+            let image = images
+                .iter()
+                .max_by_key(|image| image.width * image.height)
+                .ok_or_else(|| AppError::UnsupportedFormat {
+                    path: path.clone(),
+                    source: Some(format!("Page {} has no embedded image", number).into()),
+                })?;
+
+            let filters = image.filters.clone().unwrap_or_default();
+            let (data, extension) = if filters.last().is_some_and(|f| f == "DCTDecode") {
+                // Filters listed before DCTDecode, such as FlateDecode, wrap
+                // the JPEG data and are undone first
+                let data = if filters.len() == 1 {
+                    image.content.to_vec()
+                } else {
+                    let mut stream = document
+                        .get_object(image.id)
+                        .and_then(|object| object.as_stream())
+                        .map_err(|e| AppError::archive_corrupt(&path, e))?
+                        .clone();
+                    let outer: Vec<Object> = filters[..filters.len() - 1]
+                        .iter()
+                        .map(|filter| Object::Name(filter.as_bytes().to_vec()))
+                        .collect();
+                    stream.dict.set("Filter", outer);
+                    stream
+                        .decompressed_content()
+                        .map_err(|e| AppError::archive_corrupt(&path, e))?
+                };
+                (data, "jpg")
+            } else {
+                let raw = if filters.is_empty() {
+                    image.content.to_vec()
+                } else {
+                    document
+                        .get_object(image.id)
+                        .and_then(|object| object.as_stream())
+                        .and_then(|stream| stream.decompressed_content())
+                        .map_err(|e| AppError::archive_corrupt(&path, e))?
+                };
+
+                let (width, height) = (image.width as u32, image.height as u32);
+                let pixels = width as usize * height as usize;
+                let decoded = match image.bits_per_component {
+                    Some(8) | None if raw.len() == pixels * 3 => {
+                        RgbImage::from_raw(width, height, raw).map(DynamicImage::ImageRgb8)
+                    }
+                    Some(8) | None if raw.len() == pixels => {
+                        GrayImage::from_raw(width, height, raw).map(DynamicImage::ImageLuma8)
+                    }
+                    _ => None,
+                };
+                let decoded = decoded.ok_or_else(|| AppError::UnsupportedFormat {
+                    path: path.clone(),
+                    source: Some(
+                        format!("Page {} uses an unsupported image encoding", number).into(),
+                    ),
+                })?;
+
+                ImageProcessor::encode(&decoded, "page.png")?
+            };
+
+            let page_path = staging.join(format!("page-{:04}.{}", number, extension));
+            fs::write(&page_path, data).map_err(|e| AppError::fs(&page_path, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Page images and the ComicInfo.xml found under a folder, at any depth.
+    /// Symlinked folders are not followed, so a link cycle cannot loop forever.
+    fn collect(folder: &Path, pages: &mut Vec<PathBuf>, comic_info: &mut Option<PathBuf>) {
+        let entries = match fs::read_dir(folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Could not read folder {}: {}", folder.display(), e);
+                return;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Hidden files and macOS resource forks are not pages
+            if name.starts_with('.') || name == "__MACOSX" {
+                continue;
+            }
+
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                Self::collect(&path, pages, comic_info);
+            } else if name.eq_ignore_ascii_case("ComicInfo.xml") {
+                comic_info.get_or_insert(path);
+            } else if Self::is_source_image(&path) {
+                pages.push(path);
+            }
+        }
+    }
+
+    fn is_source_image(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                SOURCE_IMAGE_EXTENSIONS
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            })
+    }
+
+    /// Orders paths the way people number pages, so `page2` comes before
+    /// `page10`.
+    fn natural_order(a: &Path, b: &Path) -> Ordering {
+        natord::compare_ignore_case(&a.to_string_lossy(), &b.to_string_lossy())
+    }
+
+    fn pack(
+        folder: &Path,
+        destination: &Path,
+        comic_info: Option<&ComicInfo>,
+    ) -> Result<Vec<String>, AppError> {
+        let mut pages = Vec::new();
+        let mut source_info = None;
+        Self::collect(folder, &mut pages, &mut source_info);
+        pages.sort_by(|a, b| Self::natural_order(a, b));
+
+        if pages.is_empty() {
+            return Err(AppError::archive_corrupt(
+                folder.display().to_string(),
+                "No page images found",
+            ));
+        }
+
+        let base = match &source_info {
+            Some(path) => Some(fs::read_to_string(path).map_err(|e| AppError::fs(path, e))?),
+            None => None,
+        };
+        let page_count = pages.len().to_string();
+        let xml = match (comic_info, base) {
+            (Some(info), base) => {
+                let info = ComicInfo {
+                    page_count,
+                    ..info.clone()
+                };
+                let mut xml = base.unwrap_or_else(|| COMIC_INFO_TEMPLATE.to_string());
+                for (tag, value) in info.elements() {
+                    // Empty fields leave whatever the source's own ComicInfo.xml has
+                    if !value.trim().is_empty() {
+                        ComicInfo::set_element(&mut xml, tag, value);
+                    }
+                }
+                Some(xml)
+            }
+            (None, Some(mut base)) => {
+                ComicInfo::set_element(&mut base, "PageCount", &page_count);
+                Some(base)
+            }
+            (None, None) => None,
+        };
+
+        let file = File::create(destination).map_err(|e| AppError::fs(destination, e))?;
+        let mut writer = ZipWriter::new(file);
+        let width = pages.len().to_string().len().max(3);
+        let zip_error = |e| AppError::archive_corrupt(destination.display().to_string(), e);
+        // Images are compressed already, so storing them saves time for nothing lost
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        for (index, page) in pages.iter().enumerate() {
+            let data = fs::read(page).map_err(|e| AppError::fs(page, e))?;
+            let extension = page
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(str::to_lowercase)
+                .unwrap_or_default();
+
+            let (data, extension) = match extension.as_str() {
+                "jpg" | "jpeg" => (data, "jpg"),
+                "png" => (data, "png"),
+                "webp" => (data, "webp"),
+                _ => {
+                    let image = ImageProcessor::decode(&data)
+                        .map_err(|e| AppError::from_image(page.display().to_string(), e))?;
+                    ImageProcessor::encode(&image, "page.png")?
+                }
+            };
+
+            writer
+                .start_file(
+                    format!("{:0width$}.{}", index + 1, extension, width = width),
+                    stored,
+                )
+                .map_err(zip_error)?;
+            writer
+                .write_all(&data)
+                .map_err(|e| AppError::fs(destination, e))?;
+        }
+
+        if let Some(xml) = xml {
+            writer
+                .start_file("ComicInfo.xml", SimpleFileOptions::default())
+                .map_err(zip_error)?;
+            writer
+                .write_all(xml.as_bytes())
+                .map_err(|e| AppError::fs(destination, e))?;
+        }
+
+        writer.finish().map_err(zip_error)?;
+
+        // Named like archive entries, so they can be matched to the source's
+        Ok(pages
+            .iter()
+            .map(|page| {
+                page.strip_prefix(folder)
+                    .unwrap_or(page)
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,278 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::cbz_viewer::CbzViewer;
+use crate::errors::AppError;
+use crate::file_manager::{archive_stamp, FileManager, FINGERPRINT_FILE};
+
+/// Share of pages two comics must have in common to count as near duplicates.
+pub const NEAR_DUPLICATE_THRESHOLD: f32 = 0.8;
+/// Pages found in more comics than this, such as blank pages or scanlation
+/// credits, say nothing about duplicates and are left out of the comparison.
+const MAX_PAGE_SHARES: usize = 16;
+
+/// Hashes of an archive and of its pages, stored next to each comic.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Fingerprint {
+    pub archive_size: u64,
+    pub archive_modified: u64,
+    /// SHA-256 of the archive file.
+    pub archive_hash: String,
+    /// SHA-256 over the page hashes in page order. Two archives holding the
+    /// same images share it, even when repacked or renamed.
+    pub content_hash: String,
+    /// SHA-256 of each page image, in page order.
+    pub page_hashes: Vec<String>,
+}
+
+impl Fingerprint {
+    pub fn compute(cbz_path: &str) -> Result<Self, AppError> {
+        let (archive_size, archive_modified) = archive_stamp(Path::new(cbz_path))?;
+
+        let mut file = File::open(cbz_path).map_err(|e| AppError::fs(cbz_path, e))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher).map_err(|e| AppError::fs(cbz_path, e))?;
+        let archive_hash = format!("{:x}", hasher.finalize());
+
+        let mut archive = CbzViewer::open_archive(cbz_path)?;
+        let mut content = Sha256::new();
+        let mut page_hashes = Vec::new();
+
+        for name in CbzViewer::get_image_list(cbz_path)? {
+            let mut entry = archive
+                .by_name(&name)
+                .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+            let mut hasher = Sha256::new();
+            io::copy(&mut entry, &mut hasher)
+                .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+
+            let page_hash = format!("{:x}", hasher.finalize());
+            content.update(page_hash.as_bytes());
+            page_hashes.push(page_hash);
+        }
+
+        Ok(Self {
+            archive_size,
+            archive_modified,
+            archive_hash,
+            content_hash: format!("{:x}", content.finalize()),
+            page_hashes,
+        })
+    }
+
+    /// Whether both hold the same archive or the same pages.
+    pub fn is_same(&self, other: &Fingerprint) -> bool {
+        self.archive_hash == other.archive_hash
+            || (!self.page_hashes.is_empty() && self.content_hash == other.content_hash)
+    }
+
+    /// Share of distinct pages in common, relative to the comic with more pages.
+    pub fn similarity(&self, other: &Fingerprint) -> f32 {
+        let pages: HashSet<_> = self.page_hashes.iter().collect();
+        let other_pages: HashSet<_> = other.page_hashes.iter().collect();
+        let longest = pages.len().max(other_pages.len());
+        if longest == 0 {
+            return 0.0;
+        }
+
+        pages.intersection(&other_pages).count() as f32 / longest as f32
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateKind {
+    /// Byte for byte the same archive.
+    Exact,
+    /// The same pages in a different archive.
+    Repack,
+    /// Most pages in common, such as a release with extra credit pages.
+    Near,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DuplicateCandidate {
+    pub file_name: String,
+    pub size: u64,
+    pub page_count: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// Lowest share of pages in common between two linked comics.
+    pub similarity: f32,
+    pub comics: Vec<DuplicateCandidate>,
+}
+
+/// What an incoming comic has in common with the library.
+#[derive(Default)]
+pub struct DuplicateMatch {
+    /// A comic with the same archive or the same pages.
+    pub existing: Option<String>,
+    /// Comics sharing most of their pages with it.
+    pub similar: Vec<String>,
+}
+
+pub struct DuplicateFinder;
+
+impl DuplicateFinder {
+    /// Returns the fingerprint of a library comic, hashing the archive again
+    /// only when it changed since the stored one was made.
+    pub fn fingerprint(fm: &FileManager, file_name: &str) -> Result<Fingerprint, AppError> {
+        let full_path = fm.get_full_path(file_name)?;
+        let (archive_size, archive_modified) = archive_stamp(&full_path)?;
+
+        let cached: Fingerprint = fm
+            .read_sidecar(file_name, FINGERPRINT_FILE)
+            .unwrap_or_default();
+        if cached.archive_size == archive_size
+            && cached.archive_modified == archive_modified
+            && !cached.archive_hash.is_empty()
+        {
+            return Ok(cached);
+        }
+
+        let fingerprint = Fingerprint::compute(&full_path.to_string_lossy())?;
+        if let Err(e) = fm.write_sidecar(file_name, FINGERPRINT_FILE, &fingerprint) {
+            warn!("Failed to store fingerprint for {}: {}", file_name, e);
+        }
+
+        Ok(fingerprint)
+    }
+
+    /// Compares an incoming comic with the fingerprints of the library.
+    pub fn matches(fingerprint: &Fingerprint, library: &[(String, Fingerprint)]) -> DuplicateMatch {
+        let mut found = DuplicateMatch::default();
+
+        for (file_name, existing) in library {
+            if fingerprint.is_same(existing) {
+                found.existing = Some(file_name.clone());
+                break;
+            }
+            if fingerprint.similarity(existing) >= NEAR_DUPLICATE_THRESHOLD {
+                found.similar.push(file_name.clone());
+            }
+        }
+
+        found
+    }
+
+    /// Groups the comics that are exact copies, repacks or near duplicates of
+    /// each other.
+    pub fn find(library: &[(String, Fingerprint)]) -> Vec<DuplicateGroup> {
+        let candidate = |index: usize| {
+            let (file_name, fingerprint) = &library[index];
+            DuplicateCandidate {
+                file_name: file_name.clone(),
+                size: fingerprint.archive_size,
+                page_count: fingerprint.page_hashes.len(),
+            }
+        };
+
+        let mut by_content: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (index, (_, fingerprint)) in library.iter().enumerate() {
+            if !fingerprint.page_hashes.is_empty() {
+                by_content
+                    .entry(&fingerprint.content_hash)
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        let mut groups = Vec::new();
+        for members in by_content.values().filter(|members| members.len() > 1) {
+            let first = &library[members[0]].1.archive_hash;
+            let kind = if members.iter().all(|&i| &library[i].1.archive_hash == first) {
+                DuplicateKind::Exact
+            } else {
+                DuplicateKind::Repack
+            };
+
+            groups.push(DuplicateGroup {
+                kind,
+                similarity: 1.0,
+                comics: members.iter().map(|&i| candidate(i)).collect(),
+            });
+        }
+
+        // Near duplicates are compared once per distinct content, through the
+        // pages they share, so unrelated comics are never compared at all.
+        let representatives: Vec<usize> = by_content.values().map(|members| members[0]).collect();
+        let mut comics_by_page: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (slot, &index) in representatives.iter().enumerate() {
+            let pages: HashSet<&str> = library[index]
+                .1
+                .page_hashes
+                .iter()
+                .map(String::as_str)
+                .collect();
+            for page in pages {
+                comics_by_page.entry(page).or_default().push(slot);
+            }
+        }
+
+        let mut shared_pairs: HashSet<(usize, usize)> = HashSet::new();
+        for slots in comics_by_page.values() {
+            if slots.len() > MAX_PAGE_SHARES {
+                continue;
+            }
+            for (n, &a) in slots.iter().enumerate() {
+                for &b in &slots[n + 1..] {
+                    shared_pairs.insert((a, b));
+                }
+            }
+        }
+
+        let mut parents: Vec<usize> = (0..representatives.len()).collect();
+        let mut lowest = vec![1.0f32; representatives.len()];
+        let mut shared_pairs: Vec<_> = shared_pairs.into_iter().collect();
+        shared_pairs.sort_unstable();
+
+        for (a, b) in shared_pairs {
+            let similarity = library[representatives[a]]
+                .1
+                .similarity(&library[representatives[b]].1);
+            if similarity < NEAR_DUPLICATE_THRESHOLD {
+                continue;
+            }
+
+            let (root_a, root_b) = (Self::root(&mut parents, a), Self::root(&mut parents, b));
+            let linked = lowest[root_a].min(lowest[root_b]).min(similarity);
+            parents[root_b] = root_a;
+            lowest[root_a] = linked;
+        }
+
+        let mut near: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (slot, &index) in representatives.iter().enumerate() {
+            let root = Self::root(&mut parents, slot);
+            near.entry(root).or_default().push(index);
+        }
+
+        for (root, members) in near {
+            if members.len() > 1 {
+                groups.push(DuplicateGroup {
+                    kind: DuplicateKind::Near,
+                    similarity: lowest[root],
+                    comics: members.into_iter().map(candidate).collect(),
+                });
+            }
+        }
+
+        groups
+    }
+
+    fn root(parents: &mut [usize], mut slot: usize) -> usize {
+        while parents[slot] != slot {
+            parents[slot] = parents[parents[slot]];
+            slot = parents[slot];
+        }
+        slot
+    }
+}
@@ -1,5 +1,46 @@
+use serde::Serialize;
 use thiserror::Error;
 
+use crate::config_manager::ConfigFieldError;
+
+pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Stable identifiers the frontend can match on, independent of the message text.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    ArchiveCorrupt,
+    UnsupportedFormat,
+    Duplicate,
+    NotFound,
+    MetadataParse,
+    Encode,
+    TaskFailed,
+    InvalidConfig,
+    InvalidInput,
+    PartialFailure,
+    Io,
+    Internal,
+}
+
+/// One item of a batch that failed, and why.
+#[derive(Serialize, Clone, Debug)]
+pub struct ItemError {
+    pub path: String,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl ItemError {
+    pub fn new(path: impl Into<String>, error: &AppError) -> Self {
+        Self {
+            path: path.into(),
+            code: error.code(),
+            message: error.to_string(),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("IO Error: {0}")]
@@ -11,18 +52,229 @@ pub enum AppError {
     #[error("Tauri Error: {0}")]
     Tauri(#[from] tauri::Error),
 
+    #[error("Failed to access {path}: {source}")]
+    FileSystem {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
     #[error("File not found: {path}")]
     FileNotFound { path: String },
 
+    #[error("Archive is corrupt or unreadable: {path}")]
+    ArchiveCorrupt {
+        path: String,
+        #[source]
+        source: Option<BoxedError>,
+    },
+
+    #[error("Unsupported file format: {path}")]
+    UnsupportedFormat {
+        path: String,
+        #[source]
+        source: Option<BoxedError>,
+    },
+
+    #[error("Already in the library as {existing}: {path}")]
+    Duplicate { path: String, existing: String },
+
+    #[error("Failed to parse metadata of {path}")]
+    MetadataParse {
+        path: String,
+        #[source]
+        source: Option<BoxedError>,
+    },
+
+    #[error("Failed to encode {path}")]
+    Encode {
+        path: String,
+        #[source]
+        source: Option<BoxedError>,
+    },
+
+    #[error("Background task failed: {source}")]
+    TaskFailed {
+        #[source]
+        source: BoxedError,
+    },
+
+    #[error("Invalid config: {}", field_errors(.errors))]
+    InvalidConfig { errors: Vec<ConfigFieldError> },
+
+    #[error("{message}")]
+    InvalidInput { message: String },
+
+    #[error("Failed for {} of {total} items", .failures.len())]
+    PartialFailure {
+        total: usize,
+        failures: Vec<ItemError>,
+    },
+
     #[error("{message}")]
     General { message: String },
 }
 
+fn field_errors(errors: &[ConfigFieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("{}: {}", e.field, e.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl AppError {
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            message: message.into(),
+        }
+    }
+
+    pub fn fs(path: impl AsRef<std::path::Path>, source: std::io::Error) -> Self {
+        Self::FileSystem {
+            path: path.as_ref().display().to_string(),
+            source,
+        }
+    }
+
+    pub fn archive_corrupt(path: impl Into<String>, source: impl Into<BoxedError>) -> Self {
+        Self::ArchiveCorrupt {
+            path: path.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    pub fn unsupported_format(path: impl Into<String>) -> Self {
+        Self::UnsupportedFormat {
+            path: path.into(),
+            source: None,
+        }
+    }
+
+    /// Images that cannot be decoded are either in a format we do not support
+    /// or damaged inside their archive.
+    pub fn from_image(path: impl Into<String>, error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::Unsupported(_) => Self::UnsupportedFormat {
+                path: path.into(),
+                source: Some(Box::new(error)),
+            },
+            _ => Self::archive_corrupt(path, error),
+        }
+    }
+
+    pub fn metadata_parse(path: impl Into<String>, source: impl Into<BoxedError>) -> Self {
+        Self::MetadataParse {
+            path: path.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// Writing an image or document failed, not reading anything.
+    pub fn encode(path: impl Into<String>, source: impl Into<BoxedError>) -> Self {
+        Self::Encode {
+            path: path.into(),
+            source: Some(source.into()),
+        }
+    }
+
+    /// The worker running a command went away: the pool was closed, or the
+    /// task panicked or was cancelled.
+    pub fn task_failed(source: impl Into<BoxedError>) -> Self {
+        Self::TaskFailed {
+            source: source.into(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Io(e) | Self::FileSystem { source: e, .. }
+                if e.kind() == std::io::ErrorKind::NotFound =>
+            {
+                ErrorCode::NotFound
+            }
+            Self::Io(_) | Self::FileSystem { .. } => ErrorCode::Io,
+            Self::FileNotFound { .. } => ErrorCode::NotFound,
+            Self::ArchiveCorrupt { .. } => ErrorCode::ArchiveCorrupt,
+            Self::UnsupportedFormat { .. } => ErrorCode::UnsupportedFormat,
+            Self::Duplicate { .. } => ErrorCode::Duplicate,
+            Self::MetadataParse { .. } => ErrorCode::MetadataParse,
+            Self::Encode { .. } => ErrorCode::Encode,
+            Self::TaskFailed { .. } => ErrorCode::TaskFailed,
+            Self::InvalidConfig { .. } => ErrorCode::InvalidConfig,
+            Self::InvalidInput { .. } => ErrorCode::InvalidInput,
+            Self::PartialFailure { .. } => ErrorCode::PartialFailure,
+            Self::Serde(_) | Self::Tauri(_) | Self::General { .. } => ErrorCode::Internal,
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Self::FileSystem { path, .. }
+            | Self::FileNotFound { path }
+            | Self::ArchiveCorrupt { path, .. }
+            | Self::UnsupportedFormat { path, .. }
+            | Self::Duplicate { path, .. }
+            | Self::MetadataParse { path, .. }
+            | Self::Encode { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Messages of the underlying errors, outermost first.
+    pub fn source_chain(&self) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut source = std::error::Error::source(self);
+
+        while let Some(error) = source {
+            chain.push(error.to_string());
+            source = error.source();
+        }
+
+        chain
+    }
+}
+
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        Self::General { message }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorPayload<'a> {
+    code: ErrorCode,
+    message: String,
+    path: Option<&'a str>,
+    source: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<&'a [ConfigFieldError]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failures: Option<&'a [ItemError]>,
+}
+
 impl serde::Serialize for AppError {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::ser::Serializer,
     {
-        serializer.serialize_str(&self.to_string())
+        let fields = match self {
+            Self::InvalidConfig { errors } => Some(errors.as_slice()),
+            _ => None,
+        };
+        let failures = match self {
+            Self::PartialFailure { failures, .. } => Some(failures.as_slice()),
+            _ => None,
+        };
+
+        ErrorPayload {
+            code: self.code(),
+            message: self.to_string(),
+            path: self.path(),
+            source: self.source_chain(),
+            fields,
+            failures,
+        }
+        .serialize(serializer)
     }
 }
@@ -0,0 +1,759 @@
+use chrono::Utc;
+use image::codecs::jpeg::JpegDecoder;
+use image::{ColorType, ImageDecoder};
+use log::info;
+use lopdf::{Dictionary, Stream};
+use quick_xml::escape::escape;
+use serde::Deserialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Cursor, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::cbz_viewer::{CbzViewer, ComicInfo};
+use crate::comic_settings::ReadingDirection;
+use crate::errors::AppError;
+use crate::image_processor::ImageProcessor;
+use crate::library::Library;
+use crate::optimizer::{OptimizeOptions, Optimizer, TargetFormat};
+
+const EPUB_CONTAINER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    Pdf,
+    Epub,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ExtractOptions {
+    /// First page to extract, from 0.
+    pub first_page: Option<usize>,
+    /// Last page to extract, included.
+    pub last_page: Option<usize>,
+    /// Converts pages to this format; they are copied as they are when unset.
+    pub format: Option<TargetFormat>,
+    /// 1 to 100, used by WebP and JPEG.
+    pub quality: Option<u8>,
+}
+
+/// A page read from the archive, with the size it is shown at.
+struct ExportPage {
+    name: String,
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+pub struct Exporter;
+
+impl Exporter {
+    /// Writes a library comic to `output_path` as a PDF or a fixed-layout
+    /// EPUB, one page per image, with its ComicInfo metadata and reading
+    /// direction. Returns the number of pages written.
+    pub fn export(
+        library: &Library,
+        file_name: &str,
+        format: DocumentFormat,
+        output_path: &str,
+    ) -> Result<usize, AppError> {
+        let cbz_path = library
+            .files
+            .get_full_path(file_name)?
+            .to_string_lossy()
+            .to_string();
+        let images = CbzViewer::get_image_list(&cbz_path)?;
+        if images.is_empty() {
+            return Err(AppError::archive_corrupt(
+                &cbz_path,
+                "No images found in archive",
+            ));
+        }
+
+        let metadata = library.metadata(file_name);
+        let right_to_left =
+            library.comic_settings(file_name)?.reading_direction == ReadingDirection::RightToLeft;
+        let title = metadata
+            .as_ref()
+            .map(|info| info.title.trim().to_string())
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| {
+                Path::new(file_name).file_stem().map_or_else(
+                    || file_name.to_string(),
+                    |stem| stem.to_string_lossy().to_string(),
+                )
+            });
+
+        // Pages are read one at a time as they are written, so a large comic
+        // is never held in memory whole
+        let mut archive = CbzViewer::open_archive(&cbz_path)?;
+        let pages = images
+            .iter()
+            .map(|name| Self::read_page(&mut archive, &cbz_path, name));
+
+        // The output only replaces an existing file once it is complete
+        let output = Path::new(output_path);
+        let temp_path = output.with_extension("export.tmp");
+        let written = match format {
+            DocumentFormat::Pdf => {
+                Self::write_pdf(&temp_path, pages, &title, metadata.as_ref(), right_to_left)
+            }
+            DocumentFormat::Epub => {
+                let cover = Self::cover_index(&cbz_path);
+                Self::write_epub(
+                    &temp_path,
+                    pages,
+                    images.len(),
+                    &title,
+                    metadata.as_ref(),
+                    right_to_left,
+                    cover,
+                )
+            }
+        };
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        fs::rename(&temp_path, output).map_err(|e| AppError::fs(&temp_path, e))?;
+
+        info!(
+            "Exported {} to {} ({} pages)",
+            file_name,
+            output_path,
+            images.len()
+        );
+        Ok(images.len())
+    }
+
+    /// Writes pages of a library comic into a folder, named after their page
+    /// number and zero-padded so they sort in reading order. Files already in
+    /// the folder are kept. Returns the paths written.
+    pub fn extract_pages(
+        library: &Library,
+        file_name: &str,
+        output_dir: &str,
+        options: &ExtractOptions,
+    ) -> Result<Vec<String>, AppError> {
+        let cbz_path = library
+            .files
+            .get_full_path(file_name)?
+            .to_string_lossy()
+            .to_string();
+        let images = CbzViewer::get_image_list(&cbz_path)?;
+        if images.is_empty() {
+            return Err(AppError::archive_corrupt(
+                &cbz_path,
+                "No images found in archive",
+            ));
+        }
+
+        let first = options.first_page.unwrap_or(0);
+        let last = options.last_page.unwrap_or(images.len() - 1);
+        if first > last || last >= images.len() {
+            return Err(AppError::invalid_input(format!(
+                "Pages {} to {} out of range ({} pages)",
+                first,
+                last,
+                images.len()
+            )));
+        }
+
+        let conversion = match options.format {
+            Some(format) => {
+                let conversion = OptimizeOptions {
+                    format,
+                    quality: options
+                        .quality
+                        .unwrap_or(OptimizeOptions::default().quality),
+                    ..OptimizeOptions::default()
+                };
+                conversion.validate()?;
+                Some(conversion)
+            }
+            None => None,
+        };
+
+        let output_dir = Path::new(output_dir);
+        fs::create_dir_all(output_dir).map_err(|e| AppError::fs(output_dir, e))?;
+
+        let mut archive = CbzViewer::open_archive(&cbz_path)?;
+        let width = images.len().to_string().len().max(3);
+        let mut written = Vec::with_capacity(last - first + 1);
+
+        for (index, name) in images.iter().enumerate().take(last + 1).skip(first) {
+            let mut entry = archive
+                .by_name(name)
+                .map_err(|e| AppError::archive_corrupt(&cbz_path, e))?;
+            let mut data = Vec::new();
+            entry
+                .read_to_end(&mut data)
+                .map_err(|e| AppError::archive_corrupt(&cbz_path, e))?;
+
+            let (data, extension) = match &conversion {
+                Some(conversion) => {
+                    let image = ImageProcessor::decode(&data)
+                        .map_err(|e| AppError::from_image(format!("{}/{}", cbz_path, name), e))?;
+                    (
+                        Optimizer::encode(&image, conversion, &format!("{}/{}", cbz_path, name))?,
+                        conversion.format.extension(),
+                    )
+                }
+                None => {
+                    let subtype = CbzViewer::mime_subtype(name);
+                    (data, if subtype == "jpeg" { "jpg" } else { subtype })
+                }
+            };
+
+            let stem = format!("{:0width$}", index + 1, width = width);
+            let page_path = Self::write_new_file(output_dir, &stem, extension, &data)?;
+            written.push(page_path.display().to_string());
+        }
+
+        info!(
+            "Extracted {} pages of {} to {}",
+            written.len(),
+            file_name,
+            output_dir.display()
+        );
+        Ok(written)
+    }
+
+    /// Writes `data` to `<stem>.<extension>` in `dir`, adding " (2)", " (3)"
+    /// and so on to the stem instead of overwriting a file already there.
+    fn write_new_file(
+        dir: &Path,
+        stem: &str,
+        extension: &str,
+        data: &[u8],
+    ) -> Result<PathBuf, AppError> {
+        let mut path = dir.join(format!("{}.{}", stem, extension));
+        let mut counter = 2;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(data).map_err(|e| AppError::fs(&path, e))?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    path = dir.join(format!("{} ({}).{}", stem, counter, extension));
+                    counter += 1;
+                }
+                Err(e) => return Err(AppError::fs(&path, e)),
+            }
+        }
+    }
+
+    fn read_page(
+        archive: &mut ZipArchive<BufReader<File>>,
+        cbz_path: &str,
+        name: &str,
+    ) -> Result<ExportPage, AppError> {
+        let mut entry = archive
+            .by_name(name)
+            .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+
+        let (width, height) = image::ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()
+            .map_err(|e| AppError::archive_corrupt(cbz_path, e))?
+            .into_dimensions()
+            .map_err(|e| AppError::from_image(format!("{}/{}", cbz_path, name), e))?;
+
+        Ok(ExportPage {
+            name: name.to_string(),
+            data,
+            width,
+            height,
+        })
+    }
+
+    /// One PDF page per image, sized to the image at 72 dpi. JPEG pages are
+    /// embedded as they are; other formats are stored as compressed pixels.
+    /// Each page is written out before the next one is read.
+    fn write_pdf(
+        destination: &Path,
+        pages: impl Iterator<Item = Result<ExportPage, AppError>>,
+        title: &str,
+        metadata: Option<&ComicInfo>,
+        right_to_left: bool,
+    ) -> Result<(), AppError> {
+        let path = destination.display().to_string();
+        let io_error = |e: io::Error| AppError::fs(destination, e);
+
+        let file = File::create(destination).map_err(io_error)?;
+        let mut pdf = PdfWriter::new(BufWriter::new(file)).map_err(io_error)?;
+        let catalog_id = pdf.reserve();
+        let pages_id = pdf.reserve();
+        let mut kids = Vec::new();
+
+        for page in pages {
+            let page = page?;
+            let (width, height) = (page.width, page.height);
+
+            let image_id = pdf.reserve();
+            let (image_dictionary, image_data) = Self::pdf_image(page, &path)?;
+            pdf.write_stream(image_id, &image_dictionary, &image_data)
+                .map_err(io_error)?;
+
+            let content_id = pdf.reserve();
+            let content = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q", width, height);
+            pdf.write_stream(content_id, "", content.as_bytes())
+                .map_err(io_error)?;
+
+            let page_id = pdf.reserve();
+            pdf.write_object(
+                page_id,
+                &format!(
+                    "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Contents {} 0 R \
+                     /Resources << /XObject << /Im0 {} 0 R >> >> >>",
+                    pages_id, width, height, content_id, image_id
+                ),
+            )
+            .map_err(io_error)?;
+            kids.push(format!("{} 0 R", page_id));
+        }
+
+        pdf.write_object(
+            pages_id,
+            &format!(
+                "<< /Type /Pages /Count {} /Kids [{}] >>",
+                kids.len(),
+                kids.join(" ")
+            ),
+        )
+        .map_err(io_error)?;
+
+        let direction = if right_to_left { "R2L" } else { "L2R" };
+        pdf.write_object(
+            catalog_id,
+            &format!(
+                "<< /Type /Catalog /Pages {} 0 R /ViewerPreferences << /Direction /{} >> >>",
+                pages_id, direction
+            ),
+        )
+        .map_err(io_error)?;
+
+        let mut info = vec![
+            format!("/Title {}", pdf_text(title)),
+            format!("/Creator {}", pdf_text("gihon")),
+            format!("/CreationDate ({})", Utc::now().format("D:%Y%m%d%H%M%SZ")),
+        ];
+        if let Some(metadata) = metadata {
+            for (key, value) in [
+                ("Author", metadata.writer.as_str()),
+                ("Subject", metadata.summary.as_str()),
+                ("Keywords", metadata.series.as_str()),
+            ] {
+                if !value.trim().is_empty() {
+                    info.push(format!("/{} {}", key, pdf_text(value.trim())));
+                }
+            }
+        }
+        let info_id = pdf.reserve();
+        pdf.write_object(info_id, &format!("<< {} >>", info.join(" ")))
+            .map_err(io_error)?;
+
+        pdf.finish(catalog_id, info_id).map_err(io_error)
+    }
+
+    /// The image XObject for a page: its dictionary entries and its data.
+    fn pdf_image(page: ExportPage, path: &str) -> Result<(String, Vec<u8>), AppError> {
+        let image_dictionary = |color_space: &str, filter: Option<&str>| {
+            let mut dictionary = format!(
+                "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} /BitsPerComponent 8",
+                page.width, page.height, color_space
+            );
+            if let Some(filter) = filter {
+                dictionary.push_str(&format!(" /Filter /{}", filter));
+            }
+            dictionary
+        };
+
+        // CMYK JPEGs are decoded like any other image, since their colors
+        // would need an inverted decode array to show right
+        let jpeg_color = match CbzViewer::mime_subtype(&page.name) {
+            "jpeg" => JpegDecoder::new(Cursor::new(&page.data))
+                .ok()
+                .and_then(|decoder| match decoder.original_color_type() {
+                    image::ExtendedColorType::L8 => Some("DeviceGray"),
+                    image::ExtendedColorType::Rgb8 => Some("DeviceRGB"),
+                    _ => None,
+                }),
+            _ => None,
+        };
+        if let Some(color_space) = jpeg_color {
+            return Ok((image_dictionary(color_space, Some("DCTDecode")), page.data));
+        }
+
+        let image_path = format!("{}/{}", path, page.name);
+        let image =
+            ImageProcessor::decode(&page.data).map_err(|e| AppError::from_image(&image_path, e))?;
+        // PDF images have no alpha, so transparent areas are made white
+        let (color_space, pixels) = match image.color() {
+            ColorType::L8 | ColorType::L16 => ("DeviceGray", image.to_luma8().into_raw()),
+            _ => ("DeviceRGB", Optimizer::flatten_on_white(&image).into_raw()),
+        };
+
+        let mut stream = Stream::new(Dictionary::new(), pixels);
+        stream
+            .compress()
+            .map_err(|e| AppError::encode(&image_path, e))?;
+        // Left uncompressed when that would not make it smaller
+        let filter = stream.dict.get(b"Filter").is_ok().then_some("FlateDecode");
+        Ok((image_dictionary(color_space, filter), stream.content))
+    }
+
+    /// A fixed-layout EPUB 3 with one XHTML page per image, the spine running
+    /// right to left for manga.
+    fn write_epub(
+        destination: &Path,
+        pages: impl Iterator<Item = Result<ExportPage, AppError>>,
+        page_count: usize,
+        title: &str,
+        metadata: Option<&ComicInfo>,
+        right_to_left: bool,
+        cover: usize,
+    ) -> Result<(), AppError> {
+        let path = destination.display().to_string();
+        let file = File::create(destination).map_err(|e| AppError::fs(destination, e))?;
+        let mut writer = ZipWriter::new(file);
+        let zip_error = |e| AppError::archive_corrupt(&path, e);
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default();
+
+        let mut write_entry = |name: &str, options: SimpleFileOptions, data: &[u8]| {
+            writer.start_file(name, options).map_err(zip_error)?;
+            writer
+                .write_all(data)
+                .map_err(|e| AppError::fs(destination, e))
+        };
+
+        // The mimetype must come first and uncompressed
+        write_entry("mimetype", stored, b"application/epub+zip")?;
+        write_entry(
+            "META-INF/container.xml",
+            deflated,
+            EPUB_CONTAINER.as_bytes(),
+        )?;
+
+        let width = page_count.to_string().len().max(3);
+        let mut manifest = Vec::with_capacity(page_count * 2);
+        let mut spine = Vec::with_capacity(page_count);
+
+        for (index, page) in pages.enumerate() {
+            let page = page?;
+            let number = format!("{:0width$}", index + 1, width = width);
+            let subtype = CbzViewer::mime_subtype(&page.name);
+            let extension = if subtype == "jpeg" { "jpg" } else { subtype };
+            let image_name = format!("images/{}.{}", number, extension);
+
+            write_entry(&format!("OEBPS/{}", image_name), stored, &page.data)?;
+            write_entry(
+                &format!("OEBPS/pages/{}.xhtml", number),
+                deflated,
+                Self::epub_page(title, &page, &image_name).as_bytes(),
+            )?;
+
+            let properties = if index == cover {
+                " properties=\"cover-image\""
+            } else {
+                ""
+            };
+            manifest.push(format!(
+                r#"    <item id="image-{0}" href="{1}" media-type="image/{2}"{3}/>"#,
+                number, image_name, subtype, properties
+            ));
+            manifest.push(format!(
+                r#"    <item id="page-{0}" href="pages/{0}.xhtml" media-type="application/xhtml+xml"/>"#,
+                number
+            ));
+            spine.push(format!(r#"    <itemref idref="page-{}"/>"#, number));
+        }
+
+        let first_page = format!("pages/{:0width$}.xhtml", 1, width = width);
+        write_entry(
+            "OEBPS/nav.xhtml",
+            deflated,
+            Self::epub_nav(title, &first_page).as_bytes(),
+        )?;
+        write_entry(
+            "OEBPS/content.opf",
+            deflated,
+            Self::epub_package(title, metadata, right_to_left, &manifest, &spine).as_bytes(),
+        )?;
+
+        writer.finish().map_err(zip_error)?;
+        Ok(())
+    }
+
+    /// The page ComicInfo.xml marks as FrontCover, or the first page.
+    fn cover_index(cbz_path: &str) -> usize {
+        CbzViewer::read_page_info(cbz_path)
+            .ok()
+            .and_then(|pages| {
+                pages
+                    .into_iter()
+                    .find(|page| page.page_type.eq_ignore_ascii_case("FrontCover"))
+            })
+            .map_or(0, |page| page.image)
+    }
+
+    fn epub_page(title: &str, page: &ExportPage, image_name: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+  <meta name="viewport" content="width={width}, height={height}"/>
+  <style>html, body {{ margin: 0; padding: 0; }} img {{ display: block; width: {width}px; height: {height}px; }}</style>
+</head>
+<body>
+  <img src="../{image}" alt=""/>
+</body>
+</html>
+"#,
+            title = escape(title),
+            width = page.width,
+            height = page.height,
+            image = image_name,
+        )
+    }
+
+    fn epub_nav(title: &str, first_page: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+  <title>{title}</title>
+</head>
+<body>
+  <nav epub:type="toc">
+    <ol>
+      <li><a href="{first_page}">{title}</a></li>
+    </ol>
+  </nav>
+</body>
+</html>
+"#,
+            title = escape(title),
+            first_page = first_page,
+        )
+    }
+
+    /// The package document, with ComicInfo mapped onto Dublin Core and the
+    /// series onto an EPUB collection.
+    fn epub_package(
+        title: &str,
+        metadata: Option<&ComicInfo>,
+        right_to_left: bool,
+        manifest: &[String],
+        spine: &[String],
+    ) -> String {
+        let mut entries = vec![
+            format!(
+                r#"    <dc:identifier id="book-id">urn:uuid:{}</dc:identifier>"#,
+                Uuid::new_v4()
+            ),
+            format!("    <dc:title>{}</dc:title>", escape(title)),
+            "    <dc:language>und</dc:language>".to_string(),
+            format!(
+                r#"    <meta property="dcterms:modified">{}</meta>"#,
+                Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+            ),
+            r#"    <meta property="rendition:layout">pre-paginated</meta>"#.to_string(),
+            r#"    <meta property="rendition:orientation">auto</meta>"#.to_string(),
+            r#"    <meta property="rendition:spread">landscape</meta>"#.to_string(),
+        ];
+
+        if let Some(info) = metadata {
+            for writer in info.writer.split(',').map(str::trim) {
+                if !writer.is_empty() {
+                    entries.push(format!("    <dc:creator>{}</dc:creator>", escape(writer)));
+                }
+            }
+            if !info.publisher.trim().is_empty() {
+                entries.push(format!(
+                    "    <dc:publisher>{}</dc:publisher>",
+                    escape(info.publisher.trim())
+                ));
+            }
+            if !info.summary.trim().is_empty() {
+                entries.push(format!(
+                    "    <dc:description>{}</dc:description>",
+                    escape(info.summary.trim())
+                ));
+            }
+            if let Some(date) = Self::epub_date(info) {
+                entries.push(format!("    <dc:date>{}</dc:date>", date));
+            }
+            if !info.series.trim().is_empty() {
+                entries.push(format!(
+                    r#"    <meta property="belongs-to-collection" id="series">{}</meta>"#,
+                    escape(info.series.trim())
+                ));
+                entries.push(
+                    r##"    <meta refines="#series" property="collection-type">series</meta>"##
+                        .to_string(),
+                );
+                let position = if info.number.trim().is_empty() {
+                    info.volume.trim()
+                } else {
+                    info.number.trim()
+                };
+                if !position.is_empty() {
+                    entries.push(format!(
+                        r##"    <meta refines="#series" property="group-position">{}</meta>"##,
+                        escape(position)
+                    ));
+                }
+            }
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id" prefix="rendition: http://www.idpf.org/vocab/rendition/#">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{metadata}
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}
+  </manifest>
+  <spine page-progression-direction="{direction}">
+{spine}
+  </spine>
+</package>
+"#,
+            metadata = entries.join("\n"),
+            manifest = manifest.join("\n"),
+            direction = if right_to_left { "rtl" } else { "ltr" },
+            spine = spine.join("\n"),
+        )
+    }
+
+    /// The publication date as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    fn epub_date(info: &ComicInfo) -> Option<String> {
+        let year: u32 = info.year.trim().parse().ok().filter(|&year| year > 0)?;
+        let month: Option<u32> = info
+            .month
+            .trim()
+            .parse()
+            .ok()
+            .filter(|m| (1..=12).contains(m));
+        let day: Option<u32> = info
+            .day
+            .trim()
+            .parse()
+            .ok()
+            .filter(|d| (1..=31).contains(d));
+
+        Some(match (month, day) {
+            (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", year, month, day),
+            (Some(month), None) => format!("{:04}-{:02}", year, month),
+            _ => format!("{:04}", year),
+        })
+    }
+}
+
+/// A PDF text string, as UTF-16 with a byte order mark so any title survives.
+fn pdf_text(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        hex.push_str(&format!("{:04X}", unit));
+    }
+    hex.push('>');
+    hex
+}
+
+/// Writes a PDF one object at a time, so nothing has to be kept in memory
+/// until the end but the offsets for the cross-reference table. Objects are
+/// numbered from 1 in the order they are reserved.
+struct PdfWriter<W: Write> {
+    out: W,
+    position: u64,
+    /// Where each object starts, by number - 1; None until it is written.
+    offsets: Vec<Option<u64>>,
+}
+
+impl<W: Write> PdfWriter<W> {
+    fn new(out: W) -> io::Result<Self> {
+        let mut writer = Self {
+            out,
+            position: 0,
+            offsets: Vec::new(),
+        };
+        // The comment of high bytes marks the file as binary
+        writer.write_raw(b"%PDF-1.7\n%\xE2\xE3\xCF\xD3\n")?;
+        Ok(writer)
+    }
+
+    fn reserve(&mut self) -> usize {
+        self.offsets.push(None);
+        self.offsets.len()
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        self.out.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    fn write_object(&mut self, id: usize, body: &str) -> io::Result<()> {
+        self.offsets[id - 1] = Some(self.position);
+        self.write_raw(format!("{} 0 obj\n{}\nendobj\n", id, body).as_bytes())
+    }
+
+    /// `dictionary` holds the entries of the stream dictionary except Length.
+    fn write_stream(&mut self, id: usize, dictionary: &str, data: &[u8]) -> io::Result<()> {
+        self.offsets[id - 1] = Some(self.position);
+        self.write_raw(
+            format!(
+                "{} 0 obj\n<< {} /Length {} >>\nstream\n",
+                id,
+                dictionary,
+                data.len()
+            )
+            .as_bytes(),
+        )?;
+        self.write_raw(data)?;
+        self.write_raw(b"\nendstream\nendobj\n")
+    }
+
+    /// Writes the cross-reference table and trailer. Entries are exactly 20
+    /// bytes each, as the format requires.
+    fn finish(mut self, root: usize, info: usize) -> io::Result<()> {
+        let xref_start = self.position;
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            match offset {
+                Some(offset) => xref.push_str(&format!("{:010} 00000 n \n", offset)),
+                None => xref.push_str("0000000000 00000 f \n"),
+            }
+        }
+        xref.push_str(&format!(
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            info,
+            xref_start
+        ));
+        self.write_raw(xref.as_bytes())?;
+        self.out.flush()
+    }
+}
@@ -1,113 +1,217 @@
+use log::warn;
 use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::UNIX_EPOCH;
 use std::{fs, path::Path};
 use tauri::{AppHandle, Manager};
 
 use crate::cbz_viewer::{CbzViewer, ComicInfo};
+use crate::config_manager::ImportMode;
+use crate::errors::AppError;
 use base64::engine::general_purpose;
 use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use uuid::Uuid;
+
+pub const SETTINGS_FILE: &str = "settings.json";
+pub const CROP_BOXES_FILE: &str = "crop_boxes.json";
+pub const PAGE_TABLE_FILE: &str = "pages.json";
+pub const STRIP_POSITION_FILE: &str = "strip.json";
+pub const PROGRESS_FILE: &str = "progress.json";
+pub const BOOKMARKS_FILE: &str = "bookmarks.json";
+pub const FINGERPRINT_FILE: &str = "fingerprint.json";
+pub const INTEGRITY_FILE: &str = "integrity.json";
+pub const OPTIMIZE_FILE: &str = "optimize.json";
+
+/// Writer stored for comics imported without a ComicInfo.xml.
+pub const UNKNOWN_WRITER: &str = "Unknown";
+
+/// Size and modification time of an archive, used to invalidate derived data.
+/// The time is in nanoseconds, since a rewrite that keeps the size can land
+/// within the same second as the previous one.
+pub fn archive_stamp(path: &Path) -> Result<(u64, u64), AppError> {
+    let metadata = fs::metadata(path).map_err(|e| AppError::fs(path, e))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+
+    Ok((metadata.len(), modified))
+}
+
 pub struct FileManager {
     pub directory: PathBuf,
+    /// Held across a read, change and write of a sidecar that several page
+    /// loads can update at once.
+    sidecar_lock: Mutex<()>,
 }
 
 impl FileManager {
-    pub fn new(app: &AppHandle) -> Result<Self, String> {
-        let mut data_dir = app
-            .path()
-            .app_data_dir()
-            .map_err(|e| e.to_string())
-            .unwrap();
-
-        fs::create_dir_all(&data_dir).map_err(|e| e.to_string())?;
+    pub fn new(app: &AppHandle) -> Result<Self, AppError> {
+        let mut data_dir = app.path().app_data_dir()?;
 
         data_dir.push("comics");
-        fs::create_dir_all(&data_dir).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&data_dir).map_err(|e| AppError::fs(&data_dir, e))?;
 
         Ok(Self {
             directory: data_dir,
+            sidecar_lock: Mutex::new(()),
         })
     }
 
-    pub fn get_full_path(&self, file_name: &str) -> Result<PathBuf, String> {
-        let file = Path::new(file_name);
-        let file_stem = file
+    fn file_stem(file_name: &str) -> Result<&str, AppError> {
+        Path::new(file_name)
             .file_stem()
-            .ok_or("Invalid file name")?
-            .to_str()
-            .ok_or("Invalid file name")?;
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| AppError::invalid_input(format!("Invalid file name: {}", file_name)))
+    }
+
+    pub fn get_full_path(&self, file_name: &str) -> Result<PathBuf, AppError> {
+        let file_stem = Self::file_stem(file_name)?;
 
         Ok(self.directory.join(file_stem).join(file_name))
     }
 
-    pub fn add_file(&self, source_path: &str) -> Result<(), String> {
-        let source = Path::new(source_path);
+    pub fn get_comic_folder(&self, file_name: &str) -> Result<PathBuf, AppError> {
+        let file_stem = Self::file_stem(file_name)?;
 
-        let file_stem = source
-            .file_stem()
-            .ok_or("Invalid file name")?
-            .to_str()
-            .ok_or("Invalid file name")?;
-        let file_name = source
-            .file_name()
-            .ok_or("Invalid file name")?
-            .to_str()
-            .ok_or("Invalid file name")?;
-
-        let folder_path = self.directory.join(file_stem);
-        fs::create_dir_all(&folder_path).map_err(|e| e.to_string())?;
-
-        let destination_path = folder_path.join(file_name);
-
-        if destination_path.exists() {
-            return Err("File already exists in the library".to_string());
+        Ok(self.directory.join(file_stem))
+    }
+
+    /// Reads a JSON file stored next to a comic, falling back to the default
+    /// value when it has not been written yet.
+    pub fn read_sidecar<T: DeserializeOwned + Default>(
+        &self,
+        file_name: &str,
+        sidecar: &str,
+    ) -> Result<T, AppError> {
+        let sidecar_path = self.get_comic_folder(file_name)?.join(sidecar);
+        if !sidecar_path.exists() {
+            return Ok(T::default());
         }
 
-        fs::copy(source_path, &destination_path).map_err(|e| e.to_string())?;
-
-        let comic_info =
-            match CbzViewer::read_comic_info(destination_path.to_str().ok_or("Invalid path")?) {
-                Ok(info) => info,
-                Err(_) => {
-                    println!("Warning: Could not read ComicInfo.xml, using placeholder data.");
-                    ComicInfo {
-                        title: file_stem.to_string(),
-                        series: "".to_string(),
-                        number: "".to_string(),
-                        volume: "".to_string(),
-                        summary: "".to_string(),
-                        year: "".to_string(),
-                        month: "".to_string(),
-                        day: "".to_string(),
-                        writer: "Unknown".to_string(),
-                        publisher: "".to_string(),
-                        page_count: "".to_string(),
-                    }
+        let data = fs::read_to_string(&sidecar_path).map_err(|e| AppError::fs(&sidecar_path, e))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn write_sidecar<T: Serialize>(
+        &self,
+        file_name: &str,
+        sidecar: &str,
+        value: &T,
+    ) -> Result<(), AppError> {
+        let folder_path = self.get_comic_folder(file_name)?;
+        if !folder_path.exists() {
+            return Err(AppError::FileNotFound {
+                path: file_name.to_string(),
+            });
+        }
+
+        // Written aside and renamed over the old one, so readers never see
+        // half a file
+        let sidecar_path = folder_path.join(sidecar);
+        let temp_path = folder_path.join(format!("{}.{}.tmp", sidecar, Uuid::new_v4()));
+        let data = serde_json::to_string_pretty(value)?;
+        fs::write(&temp_path, data).map_err(|e| AppError::fs(&temp_path, e))?;
+        fs::rename(&temp_path, &sidecar_path).map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            AppError::fs(&sidecar_path, e)
+        })
+    }
+
+    /// Keeps other updates of shared sidecars out until dropped. Only needed
+    /// around a read, change and write; single writes are atomic anyway.
+    pub fn lock_sidecars(&self) -> MutexGuard<'_, ()> {
+        self.sidecar_lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether the library can read an archive with this name.
+    pub fn is_supported(path: &str) -> bool {
+        Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("cbz") || ext.eq_ignore_ascii_case("zip"))
+    }
+
+    /// Picks the library file name for an import. When another comic already
+    /// uses the name, " (2)", " (3)" and so on are added to the stem.
+    fn free_file_name(&self, source_path: &str) -> Result<String, AppError> {
+        let file_stem = Self::file_stem(source_path)?;
+        let extension = Path::new(source_path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("cbz");
+
+        let mut file_name = format!("{}.{}", file_stem, extension);
+        let mut counter = 2;
+        while self.get_comic_folder(&file_name)?.exists() {
+            file_name = format!("{} ({}).{}", file_stem, counter, extension);
+            counter += 1;
+        }
+
+        Ok(file_name)
+    }
+
+    /// Copies or moves a comic into the library, returning its library file name.
+    pub fn add_file(&self, source_path: &str, import_mode: ImportMode) -> Result<String, AppError> {
+        if !Self::is_supported(source_path) {
+            return Err(AppError::unsupported_format(source_path));
+        }
+
+        let file_stem = Self::file_stem(source_path)?;
+        let file_name = self.free_file_name(source_path)?;
+        let folder_path = self.get_comic_folder(&file_name)?;
+        let destination_path = folder_path.join(&file_name);
+
+        fs::create_dir_all(&folder_path).map_err(|e| AppError::fs(&folder_path, e))?;
+        fs::copy(source_path, &destination_path).map_err(|e| AppError::fs(source_path, e))?;
+
+        let destination = destination_path.to_string_lossy();
+        let comic_info = match CbzViewer::read_comic_info(&destination) {
+            Ok(info) => info,
+            Err(e) => {
+                warn!(
+                    "Could not read ComicInfo.xml of {}, using placeholder data: {}",
+                    file_name, e
+                );
+                ComicInfo {
+                    title: file_stem.to_string(),
+                    series: "".to_string(),
+                    number: "".to_string(),
+                    volume: "".to_string(),
+                    summary: "".to_string(),
+                    year: "".to_string(),
+                    month: "".to_string(),
+                    day: "".to_string(),
+                    writer: UNKNOWN_WRITER.to_string(),
+                    publisher: "".to_string(),
+                    page_count: "".to_string(),
+                    manga: "".to_string(),
                 }
-            };
+            }
+        };
 
-        self.create_metadata_file(&folder_path, &comic_info)
-            .map_err(|e| e.to_string())?;
+        self.create_metadata_file(&folder_path, &comic_info)?;
 
-        let comic_cover =
-            CbzViewer::extract_cover_image(destination_path.to_str().ok_or("Invalid path")?)?;
+        let comic_cover = CbzViewer::extract_cover_image(&destination)?;
         if let Some(cover_image_data) = comic_cover {
-            self.copy_cover_image(&folder_path, &cover_image_data)
-                .map_err(|e| e.to_string())?;
+            self.copy_cover_image(&folder_path, &cover_image_data)?;
         }
 
-        Ok(())
-    }
+        if import_mode == ImportMode::Move {
+            fs::remove_file(source_path).map_err(|e| AppError::fs(source_path, e))?;
+        }
 
-    pub fn delete_file(&self, file_name: &str) -> Result<(), String> {
-        let file = Path::new(file_name);
-        let file_stem = file
-            .file_stem()
-            .ok_or("Invalid file name")?
-            .to_str()
-            .ok_or("Invalid file name")?;
+        Ok(file_name)
+    }
 
-        let folder_path = self.directory.join(file_stem);
+    pub fn delete_file(&self, file_name: &str) -> Result<(), AppError> {
+        let folder_path = self.get_comic_folder(file_name)?;
         if folder_path.exists() {
-            fs::remove_dir_all(&folder_path).map_err(|e| e.to_string())?;
+            fs::remove_dir_all(&folder_path).map_err(|e| AppError::fs(&folder_path, e))?;
         }
 
         Ok(())
@@ -115,71 +219,114 @@ impl FileManager {
 
     pub fn create_metadata_file(
         &self,
-        folder_path: &PathBuf,
+        folder_path: &Path,
         comic_info: &ComicInfo,
-    ) -> Result<(), String> {
+    ) -> Result<(), AppError> {
         let metadata_path = folder_path.join("metadata.json");
-        let metadata = serde_json::to_string_pretty(comic_info).map_err(|e| e.to_string())?;
-        fs::write(metadata_path, metadata).map_err(|e| e.to_string())?;
+        let metadata = serde_json::to_string_pretty(comic_info)?;
+        fs::write(&metadata_path, metadata).map_err(|e| AppError::fs(&metadata_path, e))?;
 
         Ok(())
     }
 
+    /// Returns the stored metadata of a comic, falling back to its ComicInfo.xml.
+    pub fn get_metadata(&self, file_name: &str) -> Result<ComicInfo, AppError> {
+        let metadata_path = self.get_comic_folder(file_name)?.join("metadata.json");
+        if metadata_path.exists() {
+            let metadata =
+                fs::read_to_string(&metadata_path).map_err(|e| AppError::fs(&metadata_path, e))?;
+            return serde_json::from_str(&metadata)
+                .map_err(|e| AppError::metadata_parse(metadata_path.display().to_string(), e));
+        }
+
+        let full_path = self.get_full_path(file_name)?;
+        CbzViewer::read_comic_info(&full_path.to_string_lossy())
+    }
+
     pub fn edit_metadata_file(
         &self,
-        cbz_path: &String,
+        cbz_path: &str,
         comic_info: &ComicInfo,
-    ) -> Result<(), String> {
-        let file = Path::new(cbz_path);
-        let file_stem = file
-            .file_stem()
-            .ok_or("Invalid file name")?
-            .to_str()
-            .ok_or("Invalid file name")?;
-        let metadata_path = self.directory.join(file_stem).join("metadata.json");
+    ) -> Result<(), AppError> {
+        let metadata_path = self.get_comic_folder(cbz_path)?.join("metadata.json");
         if metadata_path.exists() {
-            let metadata = serde_json::to_string_pretty(comic_info).map_err(|e| e.to_string())?;
-            fs::write(metadata_path, metadata).map_err(|e| e.to_string())?;
+            let metadata = serde_json::to_string_pretty(comic_info)?;
+            fs::write(&metadata_path, metadata).map_err(|e| AppError::fs(&metadata_path, e))?;
             Ok(())
         } else {
-            Err("Metadata file does not exist".to_string())
+            Err(AppError::FileNotFound {
+                path: metadata_path.display().to_string(),
+            })
+        }
+    }
+
+    /// Stores a new page count in the metadata of a comic whose pages changed.
+    pub fn set_page_count(&self, file_name: &str, page_count: usize) -> Result<(), AppError> {
+        let mut comic_info = self.get_metadata(file_name)?;
+        comic_info.page_count = page_count.to_string();
+        self.edit_metadata_file(file_name, &comic_info)
+    }
+
+    /// Extracts the cover of a comic again, replacing the stored one.
+    pub fn rebuild_cover(&self, file_name: &str) -> Result<(), AppError> {
+        let folder_path = self.get_comic_folder(file_name)?;
+        let full_path = self.get_full_path(file_name)?;
+
+        for extension in ["jpg", "png", "webp"] {
+            let cover_path = folder_path.join("cover").with_extension(extension);
+            if cover_path.exists() {
+                fs::remove_file(&cover_path).map_err(|e| AppError::fs(&cover_path, e))?;
+            }
         }
+
+        if let Some(cover_image_data) =
+            CbzViewer::extract_cover_image(&full_path.to_string_lossy())?
+        {
+            self.copy_cover_image(&folder_path, &cover_image_data)?;
+        }
+
+        Ok(())
     }
 
     pub fn copy_cover_image(
         &self,
-        folder_path: &PathBuf,
+        folder_path: &Path,
         cover_image_data: &str,
-    ) -> Result<(), String> {
+    ) -> Result<(), AppError> {
         let cover_path = folder_path.join("cover");
         let base64_data = cover_image_data
             .split(',')
             .nth(1)
-            .ok_or("Invalid image data")?;
+            .ok_or_else(|| AppError::invalid_input("Invalid image data"))?;
         let image_data = general_purpose::STANDARD
             .decode(base64_data)
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| AppError::invalid_input(e.to_string()))?;
 
         let extension = if cover_image_data.starts_with("data:image/jpeg") {
             "jpg"
         } else if cover_image_data.starts_with("data:image/png") {
             "png"
+        } else if cover_image_data.starts_with("data:image/webp") {
+            "webp"
         } else {
-            return Err("Unsupported image format".to_string());
+            return Err(AppError::unsupported_format(
+                cover_path.display().to_string(),
+            ));
         };
 
         let cover_path = cover_path.with_extension(extension);
-        fs::write(cover_path, image_data).map_err(|e| e.to_string())?;
+        fs::write(&cover_path, image_data).map_err(|e| AppError::fs(&cover_path, e))?;
         Ok(())
     }
 
-    pub fn list_files(&self) -> Result<Vec<String>, String> {
-        let entries = fs::read_dir(&self.directory).map_err(|e| e.to_string())?;
+    pub fn list_files(&self) -> Result<Vec<String>, AppError> {
+        let entries =
+            fs::read_dir(&self.directory).map_err(|e| AppError::fs(&self.directory, e))?;
 
         let mut files = Vec::new();
 
         for entry in entries {
-            let entry = entry.map_err(|e| e.to_string())?;
+            let entry = entry?;
             let path = entry.path();
 
             if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("cbz") {
@@ -187,15 +334,15 @@ impl FileManager {
                     files.push(file_name.to_string());
                 }
             } else if path.is_dir() {
-                let sub_entries = fs::read_dir(&path).map_err(|e| e.to_string())?;
+                let sub_entries = fs::read_dir(&path).map_err(|e| AppError::fs(&path, e))?;
                 for sub_entry in sub_entries {
-                    let sub_entry = sub_entry.map_err(|e| e.to_string())?;
+                    let sub_entry = sub_entry?;
                     let sub_path = sub_entry.path();
                     if sub_path.is_file()
-                        && sub_path.extension().and_then(|s| s.to_str()) == Some("cbz")
-                        || sub_path.extension().and_then(|s| s.to_str()) == Some("zip")
-                        || sub_path.extension().and_then(|s| s.to_str()) == Some("cbr")
-                        || sub_path.extension().and_then(|s| s.to_str()) == Some("rar")
+                        && matches!(
+                            sub_path.extension().and_then(|s| s.to_str()),
+                            Some("cbz" | "zip" | "cbr" | "rar")
+                        )
                     {
                         if let Some(file_name) = sub_path.file_name().and_then(|s| s.to_str()) {
                             files.push(file_name.to_string());
@@ -0,0 +1,356 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PageView {
+    pub page: usize,
+    pub seconds: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReadingSession {
+    pub id: String,
+    pub file_name: String,
+    pub series: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub pages_turned: usize,
+    pub page_views: Vec<PageView>,
+    /// Whether the comic was finished during this session.
+    pub completed: bool,
+}
+
+impl ReadingSession {
+    fn seconds(&self) -> f64 {
+        self.page_views.iter().map(|view| view.seconds).sum()
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PeriodStats {
+    pub period: String,
+    pub pages: usize,
+    pub volumes: usize,
+    pub seconds: f64,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ReadingStats {
+    pub total_pages: usize,
+    pub total_volumes: usize,
+    pub total_seconds: f64,
+    pub average_seconds_per_page: f64,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub per_day: Vec<PeriodStats>,
+    pub per_week: Vec<PeriodStats>,
+    pub per_series: Vec<PeriodStats>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Reading history: session records in `history.json`, rewritten only when a
+/// session starts or ends, and each session's page views appended to a JSON
+/// lines file of its own under `history/`.
+pub struct HistoryManager {
+    pub history_path: PathBuf,
+    sessions_dir: PathBuf,
+    /// Serialises every read-modify-write of the history files.
+    lock: Mutex<()>,
+}
+
+impl HistoryManager {
+    pub fn new(app: &AppHandle) -> Result<Self, AppError> {
+        let data_dir = app.path().app_data_dir()?;
+        let sessions_dir = data_dir.join("history");
+        fs::create_dir_all(&sessions_dir).map_err(|e| AppError::fs(&sessions_dir, e))?;
+
+        Ok(Self {
+            history_path: data_dir.join("history.json"),
+            sessions_dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Every session with its page views.
+    pub fn load_sessions(&self) -> Result<Vec<ReadingSession>, AppError> {
+        let _guard = self.lock();
+        let mut sessions = self.load_records()?;
+        for session in &mut sessions {
+            self.attach_page_views(session)?;
+        }
+        Ok(sessions)
+    }
+
+    pub fn session(&self, session_id: &str) -> Result<ReadingSession, AppError> {
+        let _guard = self.lock();
+        let mut session = self
+            .load_records()?
+            .into_iter()
+            .find(|session| session.id == session_id)
+            .ok_or_else(|| {
+                AppError::invalid_input(format!("Reading session not found: {}", session_id))
+            })?;
+        self.attach_page_views(&mut session)?;
+        Ok(session)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn page_views_path(&self, session_id: &str) -> PathBuf {
+        self.sessions_dir.join(format!("{}.jsonl", session_id))
+    }
+
+    /// The session records alone. Sessions written before page views moved to
+    /// their own files still carry them here.
+    fn load_records(&self) -> Result<Vec<ReadingSession>, AppError> {
+        if !self.history_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let data = fs::read_to_string(&self.history_path)
+            .map_err(|e| AppError::fs(&self.history_path, e))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Writes the records to a temporary file first, so a crash or a
+    /// concurrent reader never sees a half-written history.
+    fn save_records(&self, sessions: &[ReadingSession]) -> Result<(), AppError> {
+        let data = serde_json::to_string_pretty(sessions)?;
+        let temp_path = self.history_path.with_extension("json.tmp");
+        fs::write(&temp_path, data).map_err(|e| AppError::fs(&temp_path, e))?;
+        fs::rename(&temp_path, &self.history_path).map_err(|e| AppError::fs(&temp_path, e))
+    }
+
+    fn attach_page_views(&self, session: &mut ReadingSession) -> Result<(), AppError> {
+        let path = self.page_views_path(&session.id);
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let data = fs::read_to_string(&path).map_err(|e| AppError::fs(&path, e))?;
+        // A line cut short by a crash is skipped rather than losing the session
+        for line in data.lines().filter(|line| !line.trim().is_empty()) {
+            if let Ok(view) = serde_json::from_str::<PageView>(line) {
+                session.pages_turned += 1;
+                session.page_views.push(view);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn start_session(&self, file_name: &str, series: &str) -> Result<ReadingSession, AppError> {
+        let _guard = self.lock();
+        let mut sessions = self.load_records()?;
+
+        let session = ReadingSession {
+            id: Uuid::new_v4().to_string(),
+            file_name: file_name.to_string(),
+            series: series.to_string(),
+            started_at: Utc::now(),
+            ended_at: None,
+            pages_turned: 0,
+            page_views: Vec::new(),
+            completed: false,
+        };
+
+        let page_views_path = self.page_views_path(&session.id);
+        File::create(&page_views_path).map_err(|e| AppError::fs(&page_views_path, e))?;
+
+        sessions.push(session.clone());
+        self.save_records(&sessions)?;
+        Ok(session)
+    }
+
+    /// Appends a page view to the session's own file, leaving the rest of the
+    /// history untouched.
+    pub fn record_page_view(
+        &self,
+        session_id: &str,
+        page: usize,
+        seconds: f64,
+    ) -> Result<(), AppError> {
+        let _guard = self.lock();
+        let path = self.page_views_path(session_id);
+        if !path.exists() {
+            return Err(AppError::invalid_input(format!(
+                "Reading session not found: {}",
+                session_id
+            )));
+        }
+
+        let mut line = serde_json::to_string(&PageView {
+            page,
+            seconds: seconds.max(0.0),
+        })?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| AppError::fs(&path, e))?;
+        file.write_all(line.as_bytes())
+            .map_err(|e| AppError::fs(&path, e))
+    }
+
+    pub fn end_session(
+        &self,
+        session_id: &str,
+        completed: bool,
+    ) -> Result<ReadingSession, AppError> {
+        let _guard = self.lock();
+        let mut sessions = self.load_records()?;
+        let session = sessions
+            .iter_mut()
+            .find(|session| session.id == session_id)
+            .ok_or_else(|| {
+                AppError::invalid_input(format!("Reading session not found: {}", session_id))
+            })?;
+
+        session.ended_at = Some(Utc::now());
+        session.completed = completed;
+        let mut ended = session.clone();
+
+        self.save_records(&sessions)?;
+        self.attach_page_views(&mut ended)?;
+        Ok(ended)
+    }
+
+    pub fn stats(&self) -> Result<ReadingStats, AppError> {
+        let sessions = self.load_sessions()?;
+
+        let mut per_day: BTreeMap<NaiveDate, PeriodStats> = BTreeMap::new();
+        let mut per_week: BTreeMap<String, PeriodStats> = BTreeMap::new();
+        let mut per_series: BTreeMap<String, PeriodStats> = BTreeMap::new();
+        let mut stats = ReadingStats::default();
+
+        for session in &sessions {
+            let date = session.started_at.with_timezone(&Local).date_naive();
+            let week = date.format("%G-W%V").to_string();
+            let series = if session.series.is_empty() {
+                session.file_name.clone()
+            } else {
+                session.series.clone()
+            };
+
+            let seconds = session.seconds();
+            let volumes = usize::from(session.completed);
+
+            for (entry, period) in [
+                (per_day.entry(date).or_default(), date.to_string()),
+                (per_week.entry(week.clone()).or_default(), week),
+                (per_series.entry(series.clone()).or_default(), series),
+            ] {
+                entry.period = period;
+                entry.pages += session.pages_turned;
+                entry.volumes += volumes;
+                entry.seconds += seconds;
+            }
+
+            stats.total_pages += session.pages_turned;
+            stats.total_volumes += volumes;
+            stats.total_seconds += seconds;
+        }
+
+        let page_views: usize = sessions.iter().map(|s| s.page_views.len()).sum();
+        if page_views > 0 {
+            stats.average_seconds_per_page = stats.total_seconds / page_views as f64;
+        }
+
+        let reading_days: BTreeSet<NaiveDate> = per_day
+            .iter()
+            .filter(|(_, day)| day.pages > 0)
+            .map(|(date, _)| *date)
+            .collect();
+        let (current_streak, longest_streak) =
+            Self::streaks(&reading_days, Local::now().date_naive());
+        stats.current_streak = current_streak;
+        stats.longest_streak = longest_streak;
+
+        stats.per_day = per_day.into_values().collect();
+        stats.per_week = per_week.into_values().collect();
+        stats.per_series = per_series.into_values().collect();
+
+        Ok(stats)
+    }
+
+    /// Returns the current and longest runs of consecutive reading days. The
+    /// current streak is still alive if the last reading day was yesterday.
+    fn streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (u32, u32) {
+        let mut longest = 0;
+        let mut run = 0;
+        let mut previous: Option<NaiveDate> = None;
+
+        for &day in days {
+            run = match previous {
+                Some(prev) if day - prev == Duration::days(1) => run + 1,
+                _ => 1,
+            };
+            longest = longest.max(run);
+            previous = Some(day);
+        }
+
+        let current = match previous {
+            Some(last) if today - last <= Duration::days(1) => run,
+            _ => 0,
+        };
+
+        (current, longest)
+    }
+
+    pub fn export(&self, destination: &str, format: ExportFormat) -> Result<usize, AppError> {
+        let sessions = self.load_sessions()?;
+
+        let data = match format {
+            ExportFormat::Json => serde_json::to_string_pretty(&sessions)?,
+            ExportFormat::Csv => {
+                let mut csv = String::from(
+                    "session_id,file_name,series,started_at,ended_at,pages_turned,seconds,completed\n",
+                );
+                for session in &sessions {
+                    let row = [
+                        session.id.clone(),
+                        session.file_name.clone(),
+                        session.series.clone(),
+                        session.started_at.to_rfc3339(),
+                        session.ended_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+                        session.pages_turned.to_string(),
+                        format!("{:.1}", session.seconds()),
+                        session.completed.to_string(),
+                    ];
+                    let fields: Vec<String> = row.iter().map(|f| Self::csv_field(f)).collect();
+                    csv.push_str(&fields.join(","));
+                    csv.push('\n');
+                }
+                csv
+            }
+        };
+
+        fs::write(destination, data).map_err(|e| AppError::fs(destination, e))?;
+        Ok(sessions.len())
+    }
+
+    fn csv_field(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use base64::engine::general_purpose;
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage, ImageError, ImageFormat, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+
+use crate::comic_settings::CropBox;
+use crate::errors::AppError;
+use crate::page_layout::PageHalf;
+
+const JPEG_QUALITY: u8 = 90;
+// Crops that would leave less than this fraction of a side are treated as blank pages
+const MIN_CROP_FRACTION: f64 = 0.2;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    /// Fit the whole page inside the target box.
+    #[default]
+    Contain,
+    /// Fill the target box, letting the longer side overflow.
+    Cover,
+    /// Match the target width, ignoring the height.
+    Width,
+    /// Match the target height, ignoring the width.
+    Height,
+}
+
+pub struct ImageProcessor;
+
+impl ImageProcessor {
+    /// Computes the output size for a page. Pages are never upscaled, only shrunk.
+    pub fn target_size(
+        source_width: u32,
+        source_height: u32,
+        width: Option<u32>,
+        height: Option<u32>,
+        fit_mode: FitMode,
+    ) -> (u32, u32) {
+        let scale_w = width.map(|w| w as f64 / source_width as f64);
+        let scale_h = height.map(|h| h as f64 / source_height as f64);
+
+        let scale = match (fit_mode, scale_w, scale_h) {
+            (_, None, None) => 1.0,
+            (FitMode::Width, Some(sw), _) => sw,
+            (FitMode::Height, _, Some(sh)) => sh,
+            (FitMode::Cover, Some(sw), Some(sh)) => sw.max(sh),
+            (_, Some(sw), Some(sh)) => sw.min(sh),
+            (_, Some(sw), None) => sw,
+            (_, None, Some(sh)) => sh,
+        }
+        .min(1.0);
+
+        let out_w = ((source_width as f64 * scale).round() as u32).max(1);
+        let out_h = ((source_height as f64 * scale).round() as u32).max(1);
+        (out_w, out_h)
+    }
+
+    pub fn decode(image_data: &[u8]) -> Result<DynamicImage, ImageError> {
+        image::load_from_memory(image_data)
+    }
+
+    pub fn resize(
+        image: DynamicImage,
+        width: Option<u32>,
+        height: Option<u32>,
+        fit_mode: FitMode,
+    ) -> DynamicImage {
+        let (out_w, out_h) =
+            Self::target_size(image.width(), image.height(), width, height, fit_mode);
+
+        if out_w == image.width() && out_h == image.height() {
+            return image;
+        }
+
+        image.resize_exact(out_w, out_h, FilterType::Lanczos3)
+    }
+
+    /// Detects uniform-colour borders around a page. Each edge is compared to the
+    /// colour of its outermost line; a line belongs to the border while every pixel
+    /// stays within `tolerance` of that colour.
+    pub fn detect_crop_box(image: &DynamicImage, tolerance: u8) -> Option<CropBox> {
+        let gray = image.to_luma8();
+        let (width, height) = gray.dimensions();
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let row_matches = |y: u32, reference: u8| {
+            (0..width).all(|x| gray.get_pixel(x, y)[0].abs_diff(reference) <= tolerance)
+        };
+        let column_matches = |x: u32, top: u32, bottom: u32, reference: u8| {
+            (top..bottom).all(|y| gray.get_pixel(x, y)[0].abs_diff(reference) <= tolerance)
+        };
+
+        let top_ref = Self::line_reference(&gray, 0, true);
+        let top = (0..height)
+            .find(|&y| !row_matches(y, top_ref))
+            .unwrap_or(height);
+        if top == height {
+            return None;
+        }
+
+        let bottom_ref = Self::line_reference(&gray, height - 1, true);
+        let bottom = (top..height)
+            .rev()
+            .find(|&y| !row_matches(y, bottom_ref))
+            .map(|y| y + 1)
+            .unwrap_or(top);
+
+        let left_ref = Self::line_reference(&gray, 0, false);
+        let left = (0..width)
+            .find(|&x| !column_matches(x, top, bottom, left_ref))
+            .unwrap_or(width);
+
+        let right_ref = Self::line_reference(&gray, width - 1, false);
+        let right = (left..width)
+            .rev()
+            .find(|&x| !column_matches(x, top, bottom, right_ref))
+            .map(|x| x + 1)
+            .unwrap_or(left);
+
+        let crop = CropBox {
+            x: left,
+            y: top,
+            width: right.saturating_sub(left),
+            height: bottom.saturating_sub(top),
+        };
+
+        if (crop.width as f64) < width as f64 * MIN_CROP_FRACTION
+            || (crop.height as f64) < height as f64 * MIN_CROP_FRACTION
+        {
+            return None;
+        }
+
+        if crop.width == width && crop.height == height {
+            return None;
+        }
+
+        Some(crop)
+    }
+
+    fn line_reference(gray: &GrayImage, index: u32, is_row: bool) -> u8 {
+        let mut values: Vec<u8> = if is_row {
+            (0..gray.width())
+                .map(|x| gray.get_pixel(x, index)[0])
+                .collect()
+        } else {
+            (0..gray.height())
+                .map(|y| gray.get_pixel(index, y)[0])
+                .collect()
+        };
+        values.sort_unstable();
+        values[values.len() / 2]
+    }
+
+    pub fn crop(image: DynamicImage, crop_box: &CropBox) -> DynamicImage {
+        image.crop_imm(crop_box.x, crop_box.y, crop_box.width, crop_box.height)
+    }
+
+    pub fn split(image: DynamicImage, half: PageHalf) -> DynamicImage {
+        let left_width = image.width() / 2;
+        match half {
+            PageHalf::Left => image.crop_imm(0, 0, left_width, image.height()),
+            PageHalf::Right => {
+                image.crop_imm(left_width, 0, image.width() - left_width, image.height())
+            }
+        }
+    }
+
+    /// Stacks images top to bottom in a strip `width` pixels wide. Wider
+    /// images are scaled down to fit; narrower ones are centred on white
+    /// rather than scaled up, like `resize`.
+    pub fn stitch_vertical(images: Vec<DynamicImage>, width: u32) -> DynamicImage {
+        let scaled: Vec<_> = images
+            .into_iter()
+            .map(|image| {
+                if image.width() <= width {
+                    return image.to_rgb8();
+                }
+                let height =
+                    (image.height() as u64 * width as u64 / image.width() as u64).max(1) as u32;
+                image
+                    .resize_exact(width, height, FilterType::Lanczos3)
+                    .to_rgb8()
+            })
+            .collect();
+
+        let total_height = scaled
+            .iter()
+            .map(|image| image.height())
+            .sum::<u32>()
+            .max(1);
+        let mut strip = RgbImage::from_pixel(width, total_height, Rgb([255, 255, 255]));
+        let mut offset = 0;
+
+        for image in scaled {
+            let left = (width - image.width()) / 2;
+            image::imageops::replace(&mut strip, &image, left as i64, offset as i64);
+            offset += image.height();
+        }
+
+        DynamicImage::ImageRgb8(strip)
+    }
+
+    /// Encodes a processed page. PNG sources stay lossless, everything else becomes JPEG.
+    /// Returns the encoded bytes and the file extension used.
+    pub fn encode(
+        image: &DynamicImage,
+        source_name: &str,
+    ) -> Result<(Vec<u8>, &'static str), AppError> {
+        let mut buffer = Vec::new();
+
+        if source_name.to_lowercase().ends_with(".png") {
+            image
+                .write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+                .map_err(|e| AppError::encode(source_name, e))?;
+            return Ok((buffer, "png"));
+        }
+
+        let rgb = image.to_rgb8();
+        JpegEncoder::new_with_quality(&mut buffer, JPEG_QUALITY)
+            .encode_image(&rgb)
+            .map_err(|e| AppError::encode(source_name, e))?;
+        Ok((buffer, "jpg"))
+    }
+
+    pub fn to_data_url(image_data: &[u8], extension: &str) -> String {
+        let mime_type = match extension {
+            "png" => "image/png",
+            "webp" => "image/webp",
+            "gif" => "image/gif",
+            _ => "image/jpeg",
+        };
+
+        format!(
+            "data:{};base64,{}",
+            mime_type,
+            general_purpose::STANDARD.encode(image_data)
+        )
+    }
+}
@@ -0,0 +1,130 @@
+use log::warn;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::converter::Converter;
+use crate::errors::{AppError, ErrorCode};
+use crate::file_manager::FileManager;
+use crate::jobs::{Job, JobItem, JobItemStatus};
+use crate::library::Library;
+
+/// Extensions picked up when walking a folder. RAR and 7z archives and PDFs
+/// are converted to CBZ on import.
+pub const COMIC_EXTENSIONS: [&str; 7] = ["cbz", "zip", "cbr", "rar", "cb7", "7z", "pdf"];
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ImportIssue {
+    pub path: String,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    /// Imported, but close to comics already in the library.
+    pub flagged: Vec<ImportIssue>,
+    pub skipped: Vec<ImportIssue>,
+    pub failed: Vec<ImportIssue>,
+}
+
+pub struct Importer;
+
+impl Importer {
+    /// Imports one comic. CBZ and ZIP archives are added as they are; RAR and
+    /// 7z archives and PDFs are converted to CBZ first and the source is left
+    /// in place.
+    /// Returns the new file name and the comics sharing most of its pages.
+    pub fn import(library: &Library, path: &str) -> Result<(String, Vec<String>), AppError> {
+        if FileManager::is_supported(path) {
+            library.add_file(path)
+        } else if Converter::can_convert(Path::new(path)) {
+            Converter::import(library, Path::new(path), false)
+        } else {
+            Err(AppError::unsupported_format(path))
+        }
+    }
+
+    /// Expands dropped paths into the files to import. Directories are walked
+    /// recursively for comic archives; files are imported as given.
+    pub fn collect(paths: &[String]) -> Vec<JobItem> {
+        let mut items = Vec::new();
+
+        for path in paths {
+            if Path::new(path).is_dir() {
+                Self::walk(Path::new(path), &mut items);
+            } else {
+                items.push(JobItem::file(path));
+            }
+        }
+
+        items
+    }
+
+    fn walk(dir: &Path, items: &mut Vec<JobItem>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Could not read folder {}: {}", dir.display(), e);
+                return;
+            }
+        };
+
+        let mut paths: Vec<_> = entries.flatten().collect();
+        paths.sort_by_key(|entry| entry.file_name());
+
+        for entry in paths {
+            let path = entry.path();
+            // Symlinked folders are not followed, so a link cycle cannot loop forever
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                Self::walk(&path, items);
+            } else if Self::is_comic(&path) {
+                items.push(JobItem::file(&path.to_string_lossy()));
+            }
+        }
+    }
+
+    fn is_comic(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                COMIC_EXTENSIONS
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            })
+    }
+
+    pub fn report(job: &Job) -> ImportReport {
+        let mut report = ImportReport::default();
+
+        for result in &job.results {
+            let issue = result.error.as_ref().map(|error| ImportIssue {
+                path: result.item.clone(),
+                code: error.code,
+                message: error.message.clone(),
+            });
+
+            if let Some(note) = &result.note {
+                report.flagged.push(ImportIssue {
+                    path: result.item.clone(),
+                    code: ErrorCode::Duplicate,
+                    message: note.clone(),
+                });
+            }
+
+            match (result.status, issue) {
+                (JobItemStatus::Done, _) => report.imported.push(result.item.clone()),
+                (JobItemStatus::Skipped, Some(issue)) => report.skipped.push(issue),
+                (JobItemStatus::Failed, Some(issue)) => report.failed.push(issue),
+                (_, None) => {}
+            }
+        }
+
+        report
+    }
+}
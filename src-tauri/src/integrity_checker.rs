@@ -0,0 +1,417 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+use zip::read::{read_zipfile_from_stream, ZipFile};
+use zip::result::ZipResult;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::cbz_viewer::CbzViewer;
+use crate::errors::AppError;
+use crate::file_manager::{archive_stamp, FileManager, INTEGRITY_FILE};
+use crate::image_processor::ImageProcessor;
+use crate::page_editor::PageEditor;
+
+const COMIC_INFO_ENTRY: &str = "ComicInfo.xml";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityIssueKind {
+    /// The archive itself cannot be opened.
+    Unreadable,
+    /// An entry cannot be read back or fails its CRC check.
+    Checksum,
+    /// A page image does not decode.
+    Decode,
+    /// ComicInfo.xml declares a different number of pages.
+    PageCount,
+    NoPages,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IntegrityIssue {
+    pub kind: IntegrityIssueKind,
+    pub entry: Option<String>,
+    pub message: String,
+}
+
+/// The outcome of verifying an archive, stored next to each comic.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IntegrityReport {
+    pub archive_size: u64,
+    pub archive_modified: u64,
+    pub checked_at: DateTime<Utc>,
+    pub entries: usize,
+    pub pages: usize,
+    /// PageCount from ComicInfo.xml, when it has one.
+    pub declared_pages: Option<usize>,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// One line describing the issues, for job results and logs.
+    pub fn summary(&self) -> String {
+        let messages: Vec<_> = self
+            .issues
+            .iter()
+            .take(3)
+            .map(|issue| issue.message.as_str())
+            .collect();
+        let more = self.issues.len().saturating_sub(messages.len());
+
+        if more > 0 {
+            format!("{} (and {} more)", messages.join("; "), more)
+        } else {
+            messages.join("; ")
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RepairReport {
+    /// Entries written to the rebuilt archive.
+    pub recovered: usize,
+    /// Entries left out because they could not be read or decoded.
+    pub dropped: Vec<String>,
+    /// Where the original archive was kept, when it was replaced.
+    pub backup: Option<String>,
+    pub report: IntegrityReport,
+}
+
+pub struct IntegrityChecker;
+
+impl IntegrityChecker {
+    /// Reads every entry so its CRC is checked, decodes every page and compares
+    /// the page count with the one ComicInfo.xml declares.
+    pub fn verify(cbz_path: &str) -> Result<IntegrityReport, AppError> {
+        let (archive_size, archive_modified) = archive_stamp(Path::new(cbz_path))?;
+        let mut report = IntegrityReport {
+            archive_size,
+            archive_modified,
+            checked_at: Utc::now(),
+            entries: 0,
+            pages: 0,
+            declared_pages: None,
+            issues: Vec::new(),
+        };
+
+        let mut archive = match CbzViewer::open_archive(cbz_path) {
+            Ok(archive) => archive,
+            Err(e) => {
+                report.issues.push(IntegrityIssue {
+                    kind: IntegrityIssueKind::Unreadable,
+                    entry: None,
+                    message: e.source_chain().join(": "),
+                });
+                return Ok(report);
+            }
+        };
+        report.entries = archive.len();
+
+        for i in 0..archive.len() {
+            let name = archive
+                .name_for_index(i)
+                .map_or_else(|| format!("#{}", i), str::to_string);
+            // Counted by name, so a page that fails to read is not also
+            // reported as a page count mismatch
+            let is_page = CbzViewer::is_image(&name);
+            if is_page {
+                report.pages += 1;
+            }
+
+            let data = match archive.by_index(i).and_then(Self::read_entry) {
+                Ok((_, _, data)) => data,
+                Err(e) => {
+                    report.issues.push(IntegrityIssue {
+                        kind: IntegrityIssueKind::Checksum,
+                        message: format!("{} cannot be read: {}", name, e),
+                        entry: Some(name),
+                    });
+                    continue;
+                }
+            };
+
+            if !is_page {
+                continue;
+            }
+
+            if let Err(e) = ImageProcessor::decode(&data) {
+                report.issues.push(IntegrityIssue {
+                    kind: IntegrityIssueKind::Decode,
+                    message: format!("{} does not decode: {}", name, e),
+                    entry: Some(name),
+                });
+            }
+        }
+
+        if report.pages == 0 {
+            report.issues.push(IntegrityIssue {
+                kind: IntegrityIssueKind::NoPages,
+                entry: None,
+                message: "No page images found".to_string(),
+            });
+        }
+
+        report.declared_pages = CbzViewer::read_comic_info(cbz_path)
+            .ok()
+            .and_then(|info| info.page_count.trim().parse().ok())
+            .filter(|&count| count > 0);
+        if let Some(declared) = report.declared_pages {
+            if declared != report.pages {
+                report.issues.push(IntegrityIssue {
+                    kind: IntegrityIssueKind::PageCount,
+                    entry: Some(COMIC_INFO_ENTRY.to_string()),
+                    message: format!(
+                        "ComicInfo.xml declares {} pages, the archive has {}",
+                        declared, report.pages
+                    ),
+                });
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Verifies a library comic and stores the report next to it.
+    pub fn check(fm: &FileManager, file_name: &str) -> Result<IntegrityReport, AppError> {
+        let full_path = fm.get_full_path(file_name)?;
+        let report = Self::verify(&full_path.to_string_lossy())?;
+
+        if let Err(e) = fm.write_sidecar(file_name, INTEGRITY_FILE, &report) {
+            warn!("Failed to store integrity report for {}: {}", file_name, e);
+        }
+
+        Ok(report)
+    }
+
+    /// The stored report of a comic, if the archive has not changed since.
+    pub fn stored(fm: &FileManager, file_name: &str) -> Option<IntegrityReport> {
+        let full_path = fm.get_full_path(file_name).ok()?;
+        let (archive_size, archive_modified) = archive_stamp(&full_path).ok()?;
+        let report: Option<IntegrityReport> = fm.read_sidecar(file_name, INTEGRITY_FILE).ok()?;
+
+        report.filter(|report| {
+            report.archive_size == archive_size && report.archive_modified == archive_modified
+        })
+    }
+
+    /// Rebuilds an archive from the entries that can still be read and
+    /// decoded, fixing the PageCount in ComicInfo.xml to match. An archive
+    /// whose central directory is broken is rebuilt from its local file
+    /// headers. The original is kept next to it with a `.bak` extension. An
+    /// archive without problems is left untouched.
+    pub fn repair(fm: &FileManager, file_name: &str) -> Result<RepairReport, AppError> {
+        let full_path = fm.get_full_path(file_name)?;
+        let cbz_path = full_path.to_string_lossy().to_string();
+
+        let temp_path = full_path.with_extension(format!("{}.repair.tmp", Uuid::new_v4()));
+        let temp_file = File::create(&temp_path).map_err(|e| AppError::fs(&temp_path, e))?;
+        let mut writer = ZipWriter::new(temp_file);
+
+        let mut recovered = 0;
+        let mut pages = 0;
+        let mut old_pages = Vec::new();
+        let mut dropped = Vec::new();
+        let mut comic_info = None;
+        let mut info_fixed = false;
+        let mut rebuilt_from_headers = false;
+
+        let written: Result<(), AppError> = (|| {
+            rebuilt_from_headers = Self::read_entries(&cbz_path, |name, entry| {
+                if CbzViewer::is_image(&name) {
+                    old_pages.push(name.clone());
+                }
+                let Ok((is_dir, compression, data)) = entry else {
+                    dropped.push(name);
+                    return Ok(());
+                };
+
+                if is_dir {
+                    return Ok(());
+                }
+                if CbzViewer::is_image(&name) {
+                    if ImageProcessor::decode(&data).is_err() {
+                        dropped.push(name);
+                        return Ok(());
+                    }
+                    pages += 1;
+                }
+                if name.eq_ignore_ascii_case(COMIC_INFO_ENTRY) {
+                    comic_info = Some((name, compression, data));
+                    return Ok(());
+                }
+
+                let options = SimpleFileOptions::default().compression_method(compression);
+                writer
+                    .start_file(name.as_str(), options)
+                    .map_err(|e| AppError::archive_corrupt(&cbz_path, e))?;
+                writer
+                    .write_all(&data)
+                    .map_err(|e| AppError::fs(&temp_path, e))?;
+                recovered += 1;
+                Ok(())
+            })?;
+
+            if let Some((name, compression, data)) = &comic_info {
+                let xml = String::from_utf8_lossy(data);
+                let fixed = Self::fix_page_count(&xml, pages, !dropped.is_empty());
+                info_fixed = fixed != xml;
+                let options = SimpleFileOptions::default().compression_method(*compression);
+                writer
+                    .start_file(name.as_str(), options)
+                    .map_err(|e| AppError::archive_corrupt(&cbz_path, e))?;
+                writer
+                    .write_all(fixed.as_bytes())
+                    .map_err(|e| AppError::fs(&temp_path, e))?;
+                recovered += 1;
+            }
+
+            writer
+                .finish()
+                .map_err(|e| AppError::archive_corrupt(&cbz_path, e))?;
+            Ok(())
+        })();
+
+        let written = written.and_then(|_| match pages {
+            0 => Err(AppError::archive_corrupt(
+                &cbz_path,
+                "No pages could be recovered",
+            )),
+            _ => Ok(()),
+        });
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        let unchanged = dropped.is_empty() && !info_fixed && !rebuilt_from_headers;
+        if unchanged {
+            fs::remove_file(&temp_path).map_err(|e| AppError::fs(&temp_path, e))?;
+            return Ok(RepairReport {
+                recovered,
+                dropped,
+                backup: None,
+                report: Self::check(fm, file_name)?,
+            });
+        }
+
+        let backup_path = full_path.with_extension(format!(
+            "{}.bak",
+            full_path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("cbz")
+        ));
+        fs::rename(&full_path, &backup_path).map_err(|e| AppError::fs(&full_path, e))?;
+        fs::rename(&temp_path, &full_path).map_err(|e| AppError::fs(&temp_path, e))?;
+
+        // Page indexes shift when pages are dropped
+        CbzViewer::sort_pages(&mut old_pages);
+        let mut kept = 0;
+        let new_indexes: Vec<Option<usize>> = old_pages
+            .iter()
+            .map(|name| {
+                (!dropped.contains(name)).then(|| {
+                    kept += 1;
+                    kept - 1
+                })
+            })
+            .collect();
+        PageEditor::remap_sidecars(fm, file_name, &new_indexes, pages);
+
+        info!(
+            "Repaired {}: kept {} entries, dropped {}",
+            file_name,
+            recovered,
+            dropped.len()
+        );
+
+        Ok(RepairReport {
+            recovered,
+            dropped,
+            backup: Some(backup_path.display().to_string()),
+            report: Self::check(fm, file_name)?,
+        })
+    }
+
+    /// Calls `visit` with the name and contents of every entry, read through
+    /// the central directory or, when that is broken, by scanning the local
+    /// file headers from the start. Scanning stops at the first header that
+    /// does not parse, and everything after it is reported as one unreadable
+    /// entry. Returns whether the headers were scanned.
+    fn read_entries<F>(cbz_path: &str, mut visit: F) -> Result<bool, AppError>
+    where
+        F: FnMut(String, ZipResult<(bool, CompressionMethod, Vec<u8>)>) -> Result<(), AppError>,
+    {
+        if let Ok(mut archive) = CbzViewer::open_archive(cbz_path) {
+            for i in 0..archive.len() {
+                let name = archive
+                    .name_for_index(i)
+                    .map_or_else(|| format!("#{}", i), str::to_string);
+                visit(name, archive.by_index(i).and_then(Self::read_entry))?;
+            }
+            return Ok(false);
+        }
+
+        warn!(
+            "Central directory of {} is unreadable, scanning local headers",
+            cbz_path
+        );
+        let file = File::open(cbz_path).map_err(|e| AppError::fs(cbz_path, e))?;
+        let mut reader = BufReader::new(file);
+        for i in 0.. {
+            match read_zipfile_from_stream(&mut reader) {
+                Ok(Some(entry)) => {
+                    let name = entry.name().to_string();
+                    visit(name, Self::read_entry(entry))?;
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    visit(format!("#{} and later entries", i), Err(e))?;
+                    break;
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn read_entry<R: Read>(
+        mut entry: ZipFile<'_, R>,
+    ) -> ZipResult<(bool, CompressionMethod, Vec<u8>)> {
+        let mut buffer = Vec::new();
+        entry.read_to_end(&mut buffer)?;
+        Ok((entry.is_dir(), entry.compression(), buffer))
+    }
+
+    /// Sets `<PageCount>` to the pages actually present. When pages were
+    /// dropped, the `<Pages>` list no longer lines up with the images and is
+    /// removed.
+    fn fix_page_count(xml: &str, pages: usize, drop_page_list: bool) -> String {
+        let mut fixed = xml.to_string();
+
+        if let (Some(start), Some(end)) = (fixed.find("<PageCount>"), fixed.find("</PageCount>")) {
+            if start < end {
+                fixed.replace_range(start + "<PageCount>".len()..end, &pages.to_string());
+            }
+        }
+
+        if drop_page_list {
+            if let (Some(start), Some(end)) = (fixed.find("<Pages>"), fixed.find("</Pages>")) {
+                if start < end {
+                    fixed.replace_range(start..end + "</Pages>".len(), "");
+                }
+            }
+        }
+
+        fixed
+    }
+}
@@ -0,0 +1,341 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tauri::{AppHandle, Emitter, Manager};
+use uuid::Uuid;
+
+use crate::errors::{AppError, ErrorCode};
+use crate::library::{Library, WorkPool};
+
+pub const JOB_PROGRESS_EVENT: &str = "job-progress";
+/// Finished jobs kept for inspection; older ones are dropped first.
+const MAX_FINISHED_JOBS: usize = 50;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    Import,
+    Scan,
+    RebuildThumbnails,
+    Verify,
+    Convert,
+    Optimize,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    /// Finished, but at least one item failed.
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct JobProgress {
+    pub items_done: usize,
+    pub items_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub current_item: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobItemStatus {
+    Done,
+    /// Left alone on purpose, such as a duplicate or a format we cannot read.
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct JobItemError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct JobItemResult {
+    pub item: String,
+    pub status: JobItemStatus,
+    /// Why the item was skipped or failed.
+    pub error: Option<JobItemError>,
+    /// Something worth a look about an item that was done, such as a likely
+    /// duplicate.
+    pub note: Option<String>,
+}
+
+impl JobItemResult {
+    fn new(item: String, result: Result<Option<String>, AppError>) -> Self {
+        let e = match result {
+            Ok(note) => {
+                return Self {
+                    item,
+                    status: JobItemStatus::Done,
+                    error: None,
+                    note,
+                }
+            }
+            Err(e) => e,
+        };
+
+        let status = match e.code() {
+            ErrorCode::Duplicate | ErrorCode::UnsupportedFormat => JobItemStatus::Skipped,
+            _ => JobItemStatus::Failed,
+        };
+
+        Self {
+            item,
+            status,
+            error: Some(JobItemError {
+                code: e.code(),
+                message: e.to_string(),
+            }),
+            note: None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Job {
+    pub id: String,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub progress: JobProgress,
+    pub results: Vec<JobItemResult>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// The payload of a `job-progress` event. Results are left out so the events
+/// stay small for large batches; `get_job` returns them.
+#[derive(Serialize, Clone, Debug)]
+pub struct JobEvent {
+    pub id: String,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub progress: JobProgress,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl Job {
+    fn count(&self, status: JobItemStatus) -> usize {
+        self.results.iter().filter(|r| r.status == status).count()
+    }
+
+    fn event(&self) -> JobEvent {
+        JobEvent {
+            id: self.id.clone(),
+            kind: self.kind,
+            status: self.status,
+            progress: self.progress.clone(),
+            skipped: self.count(JobItemStatus::Skipped),
+            failed: self.count(JobItemStatus::Failed),
+        }
+    }
+}
+
+/// One unit of work in a job, with its size for byte progress.
+pub struct JobItem {
+    pub name: String,
+    pub bytes: u64,
+}
+
+impl JobItem {
+    pub fn file(path: &str) -> Self {
+        Self {
+            name: path.to_string(),
+            bytes: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<Vec<Job>>,
+    cancel_flags: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl JobManager {
+    /// Every known job, newest first.
+    pub fn list(&self) -> Vec<Job> {
+        lock(&self.jobs).iter().rev().cloned().collect()
+    }
+
+    pub fn get(&self, id: &str) -> Result<Job, AppError> {
+        lock(&self.jobs)
+            .iter()
+            .find(|job| job.id == id)
+            .cloned()
+            .ok_or_else(|| AppError::invalid_input(format!("Job not found: {}", id)))
+    }
+
+    /// Asks a job to stop. The item in progress finishes first.
+    pub fn cancel(&self, id: &str) -> Result<(), AppError> {
+        match lock(&self.cancel_flags).get(id) {
+            Some(flag) => {
+                flag.store(true, Ordering::Relaxed);
+                Ok(())
+            }
+            None => Err(AppError::invalid_input(format!(
+                "Job is not running: {}",
+                id
+            ))),
+        }
+    }
+
+    fn update<F>(&self, app: &AppHandle, id: &str, update: F)
+    where
+        F: FnOnce(&mut Job),
+    {
+        let event = {
+            let mut jobs = lock(&self.jobs);
+            let Some(job) = jobs.iter_mut().find(|job| job.id == id) else {
+                return;
+            };
+            update(job);
+            job.event()
+        };
+
+        if let Err(e) = app.emit(JOB_PROGRESS_EVENT, &event) {
+            warn!("Failed to emit job progress: {}", e);
+        }
+    }
+
+    fn prune_finished(&self) {
+        let mut jobs = lock(&self.jobs);
+        let finished = jobs.iter().filter(|job| job.status.is_finished()).count();
+        let mut excess = finished.saturating_sub(MAX_FINISHED_JOBS);
+
+        jobs.retain(|job| {
+            if excess > 0 && job.status.is_finished() {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Queues a job that runs `work` on each item in order, on the library
+    /// work pool, and returns it right away. `work` may return a note to keep
+    /// with the item. Progress is reported through `job-progress` events.
+    pub fn start<F>(app: &AppHandle, kind: JobKind, items: Vec<JobItem>, work: F) -> Job
+    where
+        F: Fn(&Library, &str) -> Result<Option<String>, AppError> + Send + Sync + 'static,
+    {
+        let job = Job {
+            id: Uuid::new_v4().to_string(),
+            kind,
+            status: JobStatus::Queued,
+            progress: JobProgress {
+                items_total: items.len(),
+                bytes_total: items.iter().map(|item| item.bytes).sum(),
+                ..Default::default()
+            },
+            results: Vec::with_capacity(items.len()),
+            created_at: Utc::now(),
+            finished_at: None,
+        };
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let manager = app.state::<JobManager>();
+        lock(&manager.jobs).push(job.clone());
+        lock(&manager.cancel_flags).insert(job.id.clone(), cancelled.clone());
+
+        info!(
+            "Queued {:?} job {} with {} items",
+            kind,
+            job.id,
+            items.len()
+        );
+
+        let app = app.clone();
+        let id = job.id.clone();
+        let work = Arc::new(work);
+        tauri::async_runtime::spawn(async move {
+            Self::run(&app, &id, items, &cancelled, work).await;
+        });
+
+        job
+    }
+
+    async fn run<F>(
+        app: &AppHandle,
+        id: &str,
+        items: Vec<JobItem>,
+        cancelled: &AtomicBool,
+        work: Arc<F>,
+    ) where
+        F: Fn(&Library, &str) -> Result<Option<String>, AppError> + Send + Sync + 'static,
+    {
+        let manager = app.state::<JobManager>();
+        manager.update(app, id, |job| job.status = JobStatus::Running);
+
+        for item in items {
+            if cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+
+            manager.update(app, id, |job| {
+                job.progress.current_item = Some(item.name.clone())
+            });
+
+            let name = item.name.clone();
+            let item_work = work.clone();
+            let result = Library::run(app, WorkPool::Library, move |library| {
+                item_work(library, &name)
+            })
+            .await;
+
+            let item_result = JobItemResult::new(item.name, result);
+            if let Some(error) = &item_result.error {
+                warn!(
+                    "Job {} {:?} {}: {}",
+                    id, item_result.status, item_result.item, error.message
+                );
+            }
+
+            manager.update(app, id, |job| {
+                job.progress.items_done += 1;
+                job.progress.bytes_done += item.bytes;
+                job.results.push(item_result);
+            });
+        }
+
+        let was_cancelled = cancelled.load(Ordering::Relaxed);
+        manager.update(app, id, |job| {
+            job.progress.current_item = None;
+            job.finished_at = Some(Utc::now());
+            job.status = if was_cancelled {
+                JobStatus::Cancelled
+            } else if job.count(JobItemStatus::Failed) > 0 {
+                JobStatus::Failed
+            } else {
+                JobStatus::Completed
+            };
+            info!("Job {} finished as {:?}", job.id, job.status);
+        });
+
+        lock(&manager.cancel_flags).remove(id);
+        manager.prune_finished();
+    }
+}
@@ -1,10 +1,32 @@
+mod bookmark_manager;
 mod cbz_viewer;
+mod comic_settings;
 mod commands;
 mod config_manager;
+mod converter;
+mod duplicate_finder;
 mod errors;
+mod exporter;
 mod file_manager;
+mod history_manager;
+mod image_processor;
+mod importer;
+mod integrity_checker;
+mod jobs;
+mod library;
+mod optimizer;
+mod page_cache;
+mod page_editor;
+mod page_layout;
+mod page_renderer;
+mod preferences_manager;
+mod progress_manager;
+mod volume_builder;
 
-use log::info;
+use jobs::JobManager;
+use library::{Library, WorkPool, LIBRARY_LOADED_EVENT};
+use log::{info, warn};
+use tauri::{Emitter, Manager};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -16,12 +38,38 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
+            app.manage(Library::new(app.handle())?);
+            app.manage(JobManager::default());
+
+            // Scanning a large library takes a while, so the window opens
+            // first and is told once the index is ready. Fingerprints are only
+            // needed by the duplicate check, so comics not hashed yet are done
+            // in the background afterwards rather than on the first import
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let loaded =
+                    Library::run(&handle, WorkPool::Library, |library| library.load_index()).await;
+                if let Err(e) = loaded {
+                    warn!("Failed to index the library: {}", e);
+                }
+                if let Err(e) = handle.emit(LIBRARY_LOADED_EVENT, ()) {
+                    warn!("Failed to announce the loaded library: {}", e);
+                }
+
+                let fingerprinted = Library::run(&handle, WorkPool::Library, |library| {
+                    Ok(library.fingerprints().len())
+                })
+                .await;
+                match fingerprinted {
+                    Ok(count) => info!("Fingerprinted {} comics", count),
+                    Err(e) => warn!("Failed to fingerprint the library: {}", e),
+                }
+            });
+
             info!("App setup complete");
 
             #[cfg(debug_assertions)]
             {
-                use tauri::Manager;
-
                 if let Some(window) = app.get_webview_window("main") {
                     window.open_devtools();
                     info!("DevTools opened");
@@ -33,15 +81,69 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::load_config,
             commands::save_config,
+            commands::validate_config,
             commands::add_file,
             commands::list_files,
+            commands::is_library_loading,
             commands::read_comic_info,
             commands::get_metadata,
             commands::get_cover_image,
             commands::load_image_by_index,
             commands::get_page_count,
+            commands::get_image_list,
+            commands::get_spread_plan,
+            commands::get_page_dimensions,
+            commands::get_strip_layout,
+            commands::load_strip_segment,
+            commands::get_strip_position,
+            commands::save_strip_position,
             commands::delete_file,
             commands::edit_metadata_file,
+            commands::resolve_comic_settings,
+            commands::get_comic_preferences,
+            commands::save_comic_preferences,
+            commands::get_series_preferences,
+            commands::save_series_preferences,
+            commands::get_reading_state,
+            commands::update_reading_progress,
+            commands::get_continue_reading,
+            commands::mark_comics,
+            commands::mark_series,
+            commands::list_bookmarks,
+            commands::add_bookmark,
+            commands::edit_bookmark,
+            commands::delete_bookmark,
+            commands::list_all_bookmarks,
+            commands::start_reading_session,
+            commands::record_page_view,
+            commands::end_reading_session,
+            commands::get_reading_stats,
+            commands::export_reading_history,
+            commands::start_import,
+            commands::get_import_report,
+            commands::start_library_scan,
+            commands::rebuild_thumbnails,
+            commands::find_duplicates,
+            commands::verify_archive,
+            commands::start_integrity_scan,
+            commands::list_unhealthy_comics,
+            commands::repair_archive,
+            commands::start_conversion,
+            commands::start_optimize,
+            commands::get_pending_optimize,
+            commands::confirm_optimize,
+            commands::discard_optimize,
+            commands::delete_pages,
+            commands::move_page,
+            commands::insert_pages,
+            commands::set_cover_page,
+            commands::merge_comics,
+            commands::split_comic,
+            commands::export_comic,
+            commands::extract_pages,
+            commands::list_jobs,
+            commands::get_job,
+            commands::cancel_job,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
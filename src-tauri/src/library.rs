@@ -0,0 +1,343 @@
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use tauri::{AppHandle, Manager};
+use tokio::sync::Semaphore;
+
+use crate::cbz_viewer::ComicInfo;
+use crate::comic_settings::ComicSettings;
+use crate::config_manager::{Config, ConfigManager};
+use crate::duplicate_finder::{DuplicateFinder, Fingerprint};
+use crate::errors::AppError;
+use crate::file_manager::{archive_stamp, FileManager, FINGERPRINT_FILE};
+use crate::history_manager::HistoryManager;
+use crate::page_cache::PageCache;
+use crate::preferences_manager::PreferencesManager;
+
+/// Emitted once the index built at startup is ready.
+pub const LIBRARY_LOADED_EVENT: &str = "library-loaded";
+
+/// Imports, scans and exports are disk bound, so only a couple run at once.
+const LIBRARY_WORKERS: usize = 2;
+
+/// Kinds of blocking work, each with its own concurrency limit.
+#[derive(Clone, Copy, Debug)]
+pub enum WorkPool {
+    /// Reads the reader is waiting on: pages, covers, metadata and layouts.
+    Pages,
+    /// Long-running work over whole archives or the whole library.
+    Library,
+}
+
+/// The library and everything derived from it, created once at startup and
+/// shared with every command through Tauri managed state.
+pub struct Library {
+    pub files: FileManager,
+    pub preferences: PreferencesManager,
+    pub history: HistoryManager,
+    pub page_cache: PageCache,
+    config_manager: ConfigManager,
+    config: RwLock<Config>,
+    /// Metadata of every comic in the library, keyed by file name. `None` when
+    /// a comic has neither a metadata file nor a readable ComicInfo.xml.
+    index: RwLock<BTreeMap<String, Option<ComicInfo>>>,
+    /// False until the index built in the background at startup is in place.
+    index_loaded: AtomicBool,
+    /// Fingerprints loaded so far, filled in on the first duplicate check.
+    fingerprints: RwLock<HashMap<String, Fingerprint>>,
+    /// Held from the duplicate check until an import is in the library, so two
+    /// imports of the same file cannot both get through.
+    import_lock: Mutex<()>,
+    /// Comics whose archive is being rewritten. See `lock_file`.
+    locked_files: Mutex<HashSet<String>>,
+    file_unlocked: Condvar,
+    page_permits: Semaphore,
+    library_permits: Semaphore,
+}
+
+// A panic while holding a lock leaves the data as it was before the write,
+// which is still usable, so poisoning is ignored.
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Keeps other writers away from one comic until dropped.
+pub struct FileLock<'a> {
+    library: &'a Library,
+    file_name: String,
+}
+
+impl Drop for FileLock<'_> {
+    fn drop(&mut self) {
+        let mut locked = self
+            .library
+            .locked_files
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        locked.remove(&self.file_name);
+        self.library.file_unlocked.notify_all();
+    }
+}
+
+impl Library {
+    pub fn new(app: &AppHandle) -> Result<Self, AppError> {
+        let config_manager = ConfigManager::new(app)?;
+        let config = config_manager.load_config()?;
+
+        Ok(Self {
+            files: FileManager::new(app)?,
+            preferences: PreferencesManager::new(app)?,
+            history: HistoryManager::new(app)?,
+            page_cache: PageCache::new(app, config.cache.page_cache_bytes())?,
+            config_manager,
+            config: RwLock::new(config),
+            index: RwLock::new(BTreeMap::new()),
+            index_loaded: AtomicBool::new(false),
+            fingerprints: RwLock::new(HashMap::new()),
+            import_lock: Mutex::new(()),
+            locked_files: Mutex::new(HashSet::new()),
+            file_unlocked: Condvar::new(),
+            page_permits: Semaphore::new(thread::available_parallelism().map_or(4, |n| n.get())),
+            library_permits: Semaphore::new(LIBRARY_WORKERS),
+        })
+    }
+
+    /// Runs archive or filesystem work on the blocking thread pool, so it never
+    /// holds up the IPC thread. Long imports and scans take permits from their
+    /// own pool and cannot starve page loads.
+    pub async fn run<T, F>(app: &AppHandle, pool: WorkPool, work: F) -> Result<T, AppError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Library) -> Result<T, AppError> + Send + 'static,
+    {
+        let library = app.state::<Library>();
+        let permits = match pool {
+            WorkPool::Pages => &library.page_permits,
+            WorkPool::Library => &library.library_permits,
+        };
+        let _permit = permits.acquire().await.map_err(AppError::task_failed)?;
+
+        let app = app.clone();
+        tauri::async_runtime::spawn_blocking(move || work(&app.state::<Library>()))
+            .await
+            .map_err(AppError::task_failed)?
+    }
+
+    /// Waits until no one else is rewriting the comic, then holds it until the
+    /// returned lock is dropped. Held from reading the archive until the new
+    /// one has replaced it, so a second edit never starts from a page list
+    /// that is about to change.
+    pub fn lock_file(&self, file_name: &str) -> FileLock<'_> {
+        let mut locked = self.locked_files.lock().unwrap_or_else(|e| e.into_inner());
+        while locked.contains(file_name) {
+            locked = self
+                .file_unlocked
+                .wait(locked)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        locked.insert(file_name.to_string());
+
+        FileLock {
+            library: self,
+            file_name: file_name.to_string(),
+        }
+    }
+
+    pub fn config(&self) -> Config {
+        read(&self.config).clone()
+    }
+
+    /// Validates, saves and applies a new config.
+    pub fn set_config(&self, config: Config) -> Result<(), AppError> {
+        let errors = config.validate();
+        if !errors.is_empty() {
+            return Err(AppError::InvalidConfig { errors });
+        }
+
+        self.config_manager.save_config(&config)?;
+
+        if let Err(e) = self
+            .page_cache
+            .set_max_bytes(config.cache.page_cache_bytes())
+        {
+            warn!("Failed to apply config to page cache: {}", e);
+        }
+
+        *write(&self.config) = config;
+        Ok(())
+    }
+
+    pub fn comic_settings(&self, file_name: &str) -> Result<ComicSettings, AppError> {
+        self.preferences
+            .resolve(&self.files, file_name, &self.config())
+    }
+
+    /// Builds the index for the first time. The library counts as loaded
+    /// afterwards even when the scan failed, leaving it empty.
+    pub fn load_index(&self) -> Result<(), AppError> {
+        let result = self.rebuild_index();
+        self.index_loaded.store(true, Ordering::Release);
+        result
+    }
+
+    /// Whether the startup index is still being built, in which case the
+    /// library lists fewer comics than it holds.
+    pub fn is_loading(&self) -> bool {
+        !self.index_loaded.load(Ordering::Acquire)
+    }
+
+    /// Rescans the library folder and reloads the metadata of every comic.
+    pub fn rebuild_index(&self) -> Result<(), AppError> {
+        // Imports wait for the scan, so none is dropped by swapping in an
+        // index listed before it landed, and duplicate checks see every comic
+        let _import = self.import_lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        let index: BTreeMap<_, _> = self
+            .files
+            .list_files()?
+            .into_iter()
+            .map(|file_name| {
+                let metadata = self.files.get_metadata(&file_name).ok();
+                (file_name, metadata)
+            })
+            .collect();
+
+        info!("Indexed {} comics", index.len());
+        *write(&self.index) = index;
+        Ok(())
+    }
+
+    /// Reloads the metadata of one comic after it was added or edited.
+    pub fn refresh(&self, file_name: &str) {
+        let metadata = self.files.get_metadata(file_name).ok();
+        write(&self.index).insert(file_name.to_string(), metadata);
+        write(&self.fingerprints).remove(file_name);
+    }
+
+    pub fn list_files(&self) -> Vec<String> {
+        read(&self.index).keys().cloned().collect()
+    }
+
+    pub fn contains(&self, file_name: &str) -> bool {
+        read(&self.index).contains_key(file_name)
+    }
+
+    pub fn metadata(&self, file_name: &str) -> Option<ComicInfo> {
+        read(&self.index).get(file_name).cloned().flatten()
+    }
+
+    /// File names of the comics whose series matches, ignoring case.
+    pub fn series_files(&self, series: &str) -> Vec<String> {
+        read(&self.index)
+            .iter()
+            .filter(|(_, metadata)| {
+                metadata
+                    .as_ref()
+                    .is_some_and(|info| info.series.eq_ignore_ascii_case(series))
+            })
+            .map(|(file_name, _)| file_name.clone())
+            .collect()
+    }
+
+    /// The fingerprint of one comic, read from memory or its sidecar, or
+    /// hashed now when neither is current.
+    pub fn fingerprint(&self, file_name: &str) -> Result<Fingerprint, AppError> {
+        if let Some(fingerprint) = read(&self.fingerprints).get(file_name) {
+            return Ok(fingerprint.clone());
+        }
+
+        let fingerprint = DuplicateFinder::fingerprint(&self.files, file_name)?;
+        write(&self.fingerprints).insert(file_name.to_string(), fingerprint.clone());
+        Ok(fingerprint)
+    }
+
+    /// Fingerprints of every comic in the library. Comics hashed before are
+    /// read from their sidecar; the rest are hashed now, which can take a while
+    /// the first time on a large library.
+    pub fn fingerprints(&self) -> Vec<(String, Fingerprint)> {
+        self.list_files()
+            .into_iter()
+            .filter_map(|file_name| match self.fingerprint(&file_name) {
+                Ok(fingerprint) => Some((file_name, fingerprint)),
+                Err(e) => {
+                    warn!("Could not fingerprint {}: {}", file_name, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Imports a comic unless the library already holds the same archive or the
+    /// same pages. Returns its file name and the comics sharing most of its pages.
+    pub fn add_file(&self, source_path: &str) -> Result<(String, Vec<String>), AppError> {
+        if !FileManager::is_supported(source_path) {
+            return Err(AppError::unsupported_format(source_path));
+        }
+
+        let fingerprint = Fingerprint::compute(source_path)?;
+
+        // Hashing comics never fingerprinted before is slow, so it happens
+        // before taking the lock. Once held, only comics imported or changed
+        // in the meantime are left to hash.
+        self.fingerprints();
+        let _import = self.import_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let found = DuplicateFinder::matches(&fingerprint, &self.fingerprints());
+        if let Some(existing) = found.existing {
+            return Err(AppError::Duplicate {
+                path: source_path.to_string(),
+                existing,
+            });
+        }
+
+        let import_mode = read(&self.config).library.import_mode;
+        let file_name = self.files.add_file(source_path, import_mode)?;
+
+        // The copy has its own modification time, so the stamp is taken again
+        let (archive_size, archive_modified) =
+            archive_stamp(&self.files.get_full_path(&file_name)?)?;
+        let fingerprint = Fingerprint {
+            archive_size,
+            archive_modified,
+            ..fingerprint
+        };
+        if let Err(e) = self
+            .files
+            .write_sidecar(&file_name, FINGERPRINT_FILE, &fingerprint)
+        {
+            warn!("Failed to store fingerprint for {}: {}", file_name, e);
+        }
+
+        self.refresh(&file_name);
+        write(&self.fingerprints).insert(file_name.clone(), fingerprint);
+        Ok((file_name, found.similar))
+    }
+
+    pub fn delete_file(&self, file_name: &str) -> Result<(), AppError> {
+        self.files.delete_file(file_name)?;
+
+        write(&self.index).remove(file_name);
+        write(&self.fingerprints).remove(file_name);
+        Ok(())
+    }
+
+    /// Updates the index after a comic's archive was replaced by one with a
+    /// different file name in the same folder.
+    pub fn renamed(&self, old_file_name: &str, new_file_name: &str) {
+        write(&self.index).remove(old_file_name);
+        write(&self.fingerprints).remove(old_file_name);
+        self.refresh(new_file_name);
+    }
+
+    pub fn edit_metadata(&self, file_name: &str, comic_info: &ComicInfo) -> Result<(), AppError> {
+        self.files.edit_metadata_file(file_name, comic_info)?;
+
+        write(&self.index).insert(file_name.to_string(), Some(comic_info.clone()));
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+use chrono::{DateTime, Utc};
+use image::codecs::jpeg::JpegEncoder;
+use image::ImageReader;
+use log::{info, warn};
+use oxipng::StripChunks;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::cbz_viewer::CbzViewer;
+use crate::errors::AppError;
+use crate::file_manager::{archive_stamp, FileManager, CROP_BOXES_FILE, OPTIMIZE_FILE};
+use crate::image_processor::{FitMode, ImageProcessor};
+
+/// The optimised archive waiting for confirmation, next to the original.
+const PENDING_ARCHIVE: &str = "optimized.pending";
+/// oxipng preset; higher levels take much longer for little gain on scans.
+const PNG_OPTIMIZATION_LEVEL: u8 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetFormat {
+    /// Lossless PNG optimisation.
+    #[default]
+    Png,
+    /// WebP at `quality`, lossless at 100.
+    Webp,
+    /// JPEG at `quality`.
+    Jpeg,
+}
+
+impl TargetFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Webp => "webp",
+            Self::Jpeg => "jpg",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct OptimizeOptions {
+    pub format: TargetFormat,
+    /// 1 to 100, used by WebP and JPEG.
+    pub quality: u8,
+    /// Taller pages are scaled down to this height.
+    pub max_height: Option<u32>,
+    /// Drops EXIF and other metadata chunks from pages.
+    pub strip_metadata: bool,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            format: TargetFormat::Png,
+            quality: 85,
+            max_height: None,
+            strip_metadata: true,
+        }
+    }
+}
+
+impl OptimizeOptions {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if !(1..=100).contains(&self.quality) {
+            return Err(AppError::invalid_input(format!(
+                "Quality must be between 1 and 100, got {}",
+                self.quality
+            )));
+        }
+        if self.max_height == Some(0) {
+            return Err(AppError::invalid_input("Maximum height must be above 0"));
+        }
+        Ok(())
+    }
+}
+
+/// An optimised archive waiting for the user to keep or discard it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OptimizeReport {
+    pub options: OptimizeOptions,
+    /// Size and modification time of the original when it was optimised.
+    pub archive_size: u64,
+    pub archive_modified: u64,
+    pub optimized_size: u64,
+    pub saved_bytes: u64,
+    pub pages: usize,
+    /// Pages re-encoded; the rest were already smaller as they were.
+    pub pages_changed: usize,
+    pub pages_resized: usize,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OptimizeReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "Saves {:.1} MB of {:.1} MB",
+            self.saved_bytes as f64 / 1_048_576.0,
+            self.archive_size as f64 / 1_048_576.0
+        )
+    }
+}
+
+/// A page after optimisation.
+struct OptimizedPage {
+    name: String,
+    data: Vec<u8>,
+    changed: bool,
+    resized: bool,
+}
+
+pub struct Optimizer;
+
+impl Optimizer {
+    /// Re-encodes the pages of a comic into a new archive stored next to the
+    /// original. Nothing replaces the original until `confirm` is called.
+    pub fn optimize(
+        fm: &FileManager,
+        file_name: &str,
+        options: &OptimizeOptions,
+    ) -> Result<OptimizeReport, AppError> {
+        options.validate()?;
+
+        let full_path = fm.get_full_path(file_name)?;
+        let cbz_path = full_path.to_string_lossy().to_string();
+        let (archive_size, archive_modified) = archive_stamp(&full_path)?;
+        let pending_path = fm.get_comic_folder(file_name)?.join(PENDING_ARCHIVE);
+
+        let mut archive = CbzViewer::open_archive(&cbz_path)?;
+        let file = File::create(&pending_path).map_err(|e| AppError::fs(&pending_path, e))?;
+        let mut writer = ZipWriter::new(file);
+        let zip_error = |e| AppError::archive_corrupt(&cbz_path, e);
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        let mut report = OptimizeReport {
+            options: options.clone(),
+            archive_size,
+            archive_modified,
+            optimized_size: 0,
+            saved_bytes: 0,
+            pages: 0,
+            pages_changed: 0,
+            pages_resized: 0,
+            created_at: Utc::now(),
+        };
+
+        // Pages that change format are renamed, which must not land on the
+        // name of another entry
+        let mut taken: HashSet<String> = archive
+            .file_names()
+            .map(|name| name.to_lowercase())
+            .collect();
+
+        let written: Result<(), AppError> = (|| {
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i).map_err(zip_error)?;
+                if entry.is_dir() {
+                    continue;
+                }
+
+                let name = entry.name().to_string();
+                let mut data = Vec::new();
+                entry
+                    .read_to_end(&mut data)
+                    .map_err(|e| AppError::archive_corrupt(&cbz_path, e))?;
+
+                if !CbzViewer::is_image(&name) {
+                    let options =
+                        SimpleFileOptions::default().compression_method(entry.compression());
+                    drop(entry);
+                    writer.start_file(name, options).map_err(zip_error)?;
+                    writer
+                        .write_all(&data)
+                        .map_err(|e| AppError::fs(&pending_path, e))?;
+                    continue;
+                }
+                drop(entry);
+
+                let mut page = Self::optimize_page(&name, data, options)?;
+                if page.name != name {
+                    page.name = Self::free_name(&name, &page.name, &mut taken);
+                }
+                report.pages += 1;
+                report.pages_changed += page.changed as usize;
+                report.pages_resized += page.resized as usize;
+
+                writer.start_file(page.name, stored).map_err(zip_error)?;
+                writer
+                    .write_all(&page.data)
+                    .map_err(|e| AppError::fs(&pending_path, e))?;
+            }
+
+            writer.finish().map_err(zip_error)?;
+            Ok(())
+        })();
+
+        if let Err(e) = written {
+            let _ = fs::remove_file(&pending_path);
+            return Err(e);
+        }
+
+        report.optimized_size = fs::metadata(&pending_path)
+            .map_err(|e| AppError::fs(&pending_path, e))?
+            .len();
+        report.saved_bytes = archive_size.saturating_sub(report.optimized_size);
+        fm.write_sidecar(file_name, OPTIMIZE_FILE, &report)?;
+
+        info!("Optimised {}: {}", file_name, report.summary());
+        Ok(report)
+    }
+
+    fn optimize_page(
+        name: &str,
+        data: Vec<u8>,
+        options: &OptimizeOptions,
+    ) -> Result<OptimizedPage, AppError> {
+        let name_lower = name.to_lowercase();
+        let is_png = name_lower.ends_with(".png");
+        let is_jpeg = name_lower.ends_with(".jpg") || name_lower.ends_with(".jpeg");
+
+        let height = ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+            .map(|(_, height)| height);
+        let needs_resize = options
+            .max_height
+            .zip(height)
+            .is_some_and(|(max_height, height)| height > max_height);
+
+        let encoded = if options.format == TargetFormat::Png && is_png && !needs_resize {
+            // Lossless on the original bytes, so nothing is decoded at all
+            Self::optimize_png(name, &data, options.strip_metadata)?
+        } else {
+            let mut image = ImageProcessor::decode(&data)
+                .map_err(|e| AppError::from_image(name.to_string(), e))?;
+            if needs_resize {
+                image = ImageProcessor::resize(image, None, options.max_height, FitMode::Height);
+            }
+            Self::encode(&image, options, name)?
+        };
+
+        if !needs_resize && encoded.len() >= data.len() {
+            let data = if is_jpeg && options.strip_metadata {
+                Self::strip_jpeg_exif(&data)
+            } else {
+                data
+            };
+            return Ok(OptimizedPage {
+                name: name.to_string(),
+                data,
+                changed: false,
+                resized: false,
+            });
+        }
+
+        let stem = Path::new(name).with_extension("");
+        Ok(OptimizedPage {
+            name: format!("{}.{}", stem.to_string_lossy(), options.format.extension()),
+            data: encoded,
+            changed: true,
+            resized: needs_resize,
+        })
+    }
+
+    /// `renamed` when no other entry uses it, or else the original name with
+    /// the new extension added, which keeps the page where it sorted before.
+    fn free_name(original: &str, renamed: &str, taken: &mut HashSet<String>) -> String {
+        let extension = Path::new(renamed)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut name = renamed.to_string();
+        if taken.contains(&name.to_lowercase()) {
+            name = format!("{}.{}", original, extension);
+        }
+        let mut counter = 2;
+        while taken.contains(&name.to_lowercase()) {
+            name = format!("{}_{}.{}", original, counter, extension);
+            counter += 1;
+        }
+
+        taken.insert(name.to_lowercase());
+        name
+    }
+
+    fn optimize_png(name: &str, data: &[u8], strip_metadata: bool) -> Result<Vec<u8>, AppError> {
+        let mut png_options = oxipng::Options::from_preset(PNG_OPTIMIZATION_LEVEL);
+        png_options.strip = if strip_metadata {
+            StripChunks::Safe
+        } else {
+            StripChunks::None
+        };
+
+        oxipng::optimize_from_memory(data, &png_options).map_err(|e| AppError::encode(name, e))
+    }
+
+    /// Encodes a page in the target format. Metadata is never carried over by
+    /// re-encoding. `name` only labels errors.
+    pub fn encode(
+        image: &image::DynamicImage,
+        options: &OptimizeOptions,
+        name: &str,
+    ) -> Result<Vec<u8>, AppError> {
+        match options.format {
+            TargetFormat::Png => {
+                let (data, _) = ImageProcessor::encode(image, "page.png")?;
+                Self::optimize_png(name, &data, true)
+            }
+            TargetFormat::Jpeg => {
+                let mut buffer = Vec::new();
+                JpegEncoder::new_with_quality(&mut buffer, options.quality)
+                    .encode_image(&Self::flatten_on_white(image))
+                    .map_err(|e| AppError::encode(name, e))?;
+                Ok(buffer)
+            }
+            TargetFormat::Webp => {
+                let image = if image.color().has_alpha() {
+                    image::DynamicImage::ImageRgba8(image.to_rgba8())
+                } else {
+                    image::DynamicImage::ImageRgb8(image.to_rgb8())
+                };
+                let encoder =
+                    webp::Encoder::from_image(&image).map_err(|e| AppError::encode(name, e))?;
+                let encoded = if options.quality == 100 {
+                    encoder.encode_lossless()
+                } else {
+                    encoder.encode(options.quality as f32)
+                };
+                Ok(encoded.to_vec())
+            }
+        }
+    }
+
+    /// Drops the alpha channel for formats without one. Transparent areas
+    /// become white like the page around them, instead of whatever colour
+    /// the transparent pixels happen to hold.
+    pub(crate) fn flatten_on_white(image: &image::DynamicImage) -> image::RgbImage {
+        if !image.color().has_alpha() {
+            return image.to_rgb8();
+        }
+
+        let rgba = image.to_rgba8();
+        image::RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let alpha = a as u32;
+            let blend = |channel: u8| ((channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+            image::Rgb([blend(r), blend(g), blend(b)])
+        })
+    }
+
+    /// Removes the APP1 segments holding EXIF from a JPEG without touching the
+    /// image data. Anything that does not parse is returned unchanged.
+    fn strip_jpeg_exif(data: &[u8]) -> Vec<u8> {
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return data.to_vec();
+        }
+
+        let mut stripped = vec![0xFF, 0xD8];
+        let mut pos = 2;
+
+        while pos + 4 <= data.len() && data[pos] == 0xFF {
+            let marker = data[pos + 1];
+            // Start of scan: the rest is image data
+            if marker == 0xDA {
+                break;
+            }
+
+            let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+            let end = pos + 2 + length;
+            if length < 2 || end > data.len() {
+                return data.to_vec();
+            }
+
+            let is_exif = marker == 0xE1 && data[pos + 4..end].starts_with(b"Exif\0");
+            if !is_exif {
+                stripped.extend_from_slice(&data[pos..end]);
+            }
+            pos = end;
+        }
+
+        stripped.extend_from_slice(&data[pos..]);
+        stripped
+    }
+
+    /// The optimised archive waiting for a decision, if there is one.
+    pub fn pending(fm: &FileManager, file_name: &str) -> Result<Option<OptimizeReport>, AppError> {
+        let pending_path = fm.get_comic_folder(file_name)?.join(PENDING_ARCHIVE);
+        if !pending_path.exists() {
+            return Ok(None);
+        }
+
+        fm.read_sidecar(file_name, OPTIMIZE_FILE)
+    }
+
+    /// Replaces the original with the optimised archive.
+    pub fn confirm(fm: &FileManager, file_name: &str) -> Result<OptimizeReport, AppError> {
+        let report = Self::pending(fm, file_name)?.ok_or_else(|| {
+            AppError::invalid_input(format!("No optimised archive waiting for {}", file_name))
+        })?;
+
+        let full_path = fm.get_full_path(file_name)?;
+        let (archive_size, archive_modified) = archive_stamp(&full_path)?;
+        if archive_size != report.archive_size || archive_modified != report.archive_modified {
+            Self::discard(fm, file_name)?;
+            return Err(AppError::invalid_input(format!(
+                "{} changed since it was optimised; optimise it again",
+                file_name
+            )));
+        }
+
+        let folder_path = fm.get_comic_folder(file_name)?;
+        let pending_path = folder_path.join(PENDING_ARCHIVE);
+        fs::rename(&pending_path, &full_path).map_err(|e| AppError::fs(&pending_path, e))?;
+
+        // Crop boxes are measured on the old page sizes
+        if report.pages_resized > 0 {
+            let crop_boxes = folder_path.join(CROP_BOXES_FILE);
+            if crop_boxes.exists() {
+                fs::remove_file(&crop_boxes).map_err(|e| AppError::fs(&crop_boxes, e))?;
+            }
+        }
+        if let Err(e) = fm.rebuild_cover(file_name) {
+            warn!("Failed to rebuild cover of {}: {}", file_name, e);
+        }
+
+        Self::remove_report(fm, file_name)?;
+        Ok(report)
+    }
+
+    /// Deletes the optimised archive, keeping the original.
+    pub fn discard(fm: &FileManager, file_name: &str) -> Result<(), AppError> {
+        let pending_path = fm.get_comic_folder(file_name)?.join(PENDING_ARCHIVE);
+        if pending_path.exists() {
+            fs::remove_file(&pending_path).map_err(|e| AppError::fs(&pending_path, e))?;
+        }
+
+        Self::remove_report(fm, file_name)
+    }
+
+    fn remove_report(fm: &FileManager, file_name: &str) -> Result<(), AppError> {
+        let report_path = fm.get_comic_folder(file_name)?.join(OPTIMIZE_FILE);
+        if report_path.exists() {
+            fs::remove_file(&report_path).map_err(|e| AppError::fs(&report_path, e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Optimizer;
+
+    const SOI: [u8; 2] = [0xFF, 0xD8];
+    const APP0: [u8; 8] = [0xFF, 0xE0, 0x00, 0x06, b'J', b'F', b'I', b'F'];
+    const EXIF: [u8; 12] = [
+        0xFF, 0xE1, 0x00, 0x0A, b'E', b'x', b'i', b'f', 0x00, 0x00, 0x4D, 0x4D,
+    ];
+    const SCAN: [u8; 8] = [0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9];
+
+    #[test]
+    fn strip_jpeg_exif_keeps_jpeg_without_exif() {
+        let data = [&SOI[..], &APP0, &SCAN].concat();
+        assert_eq!(Optimizer::strip_jpeg_exif(&data), data);
+    }
+
+    #[test]
+    fn strip_jpeg_exif_removes_exif_segment() {
+        let data = [&SOI[..], &APP0, &EXIF, &SCAN].concat();
+        let expected = [&SOI[..], &APP0, &SCAN].concat();
+        assert_eq!(Optimizer::strip_jpeg_exif(&data), expected);
+    }
+
+    #[test]
+    fn strip_jpeg_exif_returns_truncated_input_unchanged() {
+        let data = [&SOI[..], &APP0, &EXIF[..6]].concat();
+        assert_eq!(Optimizer::strip_jpeg_exif(&data), data);
+    }
+
+    #[test]
+    fn strip_jpeg_exif_returns_non_jpeg_unchanged() {
+        let data = b"\x89PNG\r\n".to_vec();
+        assert_eq!(Optimizer::strip_jpeg_exif(&data), data);
+    }
+}
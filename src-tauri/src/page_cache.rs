@@ -0,0 +1,169 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::file_manager::archive_stamp;
+
+const CACHE_EXTENSIONS: [&str; 2] = ["jpg", "png"];
+
+/// Feeds `Hash` output into SHA-256, whose result does not change between
+/// Rust releases the way `DefaultHasher` may, so cached pages survive updates.
+/// Integers are written little-endian whatever the platform.
+struct KeyHasher(Sha256);
+
+impl Hasher for KeyHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        u64::from_le_bytes(digest[..8].try_into().unwrap_or_default())
+    }
+}
+
+pub struct PageCache {
+    pub directory: PathBuf,
+    max_bytes: AtomicU64,
+    /// Size of the cache folder as of the last scan plus everything written
+    /// since, so a write only lists the folder once the limit looks reached.
+    /// Overwritten entries are counted twice until that scan corrects it.
+    total_bytes: AtomicU64,
+}
+
+impl PageCache {
+    pub fn new(app: &AppHandle, max_bytes: u64) -> Result<Self, AppError> {
+        let mut cache_dir = app.path().app_cache_dir()?;
+
+        cache_dir.push("pages");
+        fs::create_dir_all(&cache_dir).map_err(|e| AppError::fs(&cache_dir, e))?;
+
+        let cache = Self {
+            directory: cache_dir,
+            max_bytes: AtomicU64::new(max_bytes),
+            total_bytes: AtomicU64::new(0),
+        };
+        let total_bytes = cache.entries()?.iter().map(|(_, size, _)| size).sum();
+        cache.total_bytes.store(total_bytes, Ordering::Relaxed);
+        Ok(cache)
+    }
+
+    /// Builds a cache key for a rendered page. The archive size and modification
+    /// time are part of the key so edited archives never serve stale pages.
+    pub fn cache_key(cbz_path: &Path, request: &impl Hash) -> Result<String, AppError> {
+        let (size, modified) = archive_stamp(cbz_path)?;
+
+        let mut hasher = KeyHasher(Sha256::new());
+        cbz_path.hash(&mut hasher);
+        size.hash(&mut hasher);
+        modified.hash(&mut hasher);
+        request.hash(&mut hasher);
+
+        Ok(format!("{:x}", hasher.0.finalize()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<(Vec<u8>, &'static str)> {
+        CACHE_EXTENSIONS.iter().find_map(|ext| {
+            fs::read(self.directory.join(format!("{}.{}", key, ext)))
+                .ok()
+                .map(|data| (data, *ext))
+        })
+    }
+
+    /// Stores an entry through a temporary file, so a concurrent `get` never
+    /// reads a page that is only partly written.
+    pub fn put(&self, key: &str, image_data: &[u8], extension: &str) -> Result<(), AppError> {
+        let path = self.directory.join(format!("{}.{}", key, extension));
+        let temp_path =
+            self.directory
+                .join(format!("{}.{}.{}.tmp", key, extension, Uuid::new_v4()));
+
+        fs::write(&temp_path, image_data).map_err(|e| AppError::fs(&temp_path, e))?;
+        if let Err(e) = fs::rename(&temp_path, &path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(AppError::fs(&path, e));
+        }
+
+        self.total_bytes
+            .fetch_add(image_data.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Applies a new size limit, evicting entries right away if the cache no longer fits.
+    pub fn set_max_bytes(&self, max_bytes: u64) -> Result<(), AppError> {
+        self.max_bytes.store(max_bytes, Ordering::Relaxed);
+        self.prune()
+    }
+
+    /// Deletes the least recently written entries until the cache fits in
+    /// `max_bytes`. The folder is only listed when the running total is over
+    /// the limit.
+    pub fn prune(&self) -> Result<(), AppError> {
+        let max_bytes = self.max_bytes.load(Ordering::Relaxed);
+        if self.total_bytes.load(Ordering::Relaxed) <= max_bytes {
+            return Ok(());
+        }
+
+        let mut entries = self.entries()?;
+        let mut total_size = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by_key(|(modified, _, _)| *modified);
+
+        let mut removed = Ok(());
+        for (_, size, path) in entries {
+            if total_size <= max_bytes {
+                break;
+            }
+            if let Err(e) = fs::remove_file(&path) {
+                removed = Err(AppError::fs(&path, e));
+                break;
+            }
+            total_size -= size;
+        }
+
+        self.total_bytes.store(total_size, Ordering::Relaxed);
+        removed
+    }
+
+    /// Every file in the cache folder with its modification time and size.
+    fn entries(&self) -> Result<Vec<(SystemTime, u64, PathBuf)>, AppError> {
+        let mut entries = Vec::new();
+
+        for entry in fs::read_dir(&self.directory).map_err(|e| AppError::fs(&self.directory, e))? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            entries.push((
+                metadata.modified().unwrap_or(UNIX_EPOCH),
+                metadata.len(),
+                entry.path(),
+            ));
+        }
+
+        Ok(entries)
+    }
+}
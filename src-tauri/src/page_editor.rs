@@ -0,0 +1,349 @@
+use log::{info, warn};
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::bookmark_manager::BookmarkManager;
+use crate::cbz_viewer::{CbzViewer, ComicInfo, ComicPageInfo};
+use crate::errors::AppError;
+use crate::file_manager::{FileManager, CROP_BOXES_FILE, PAGE_TABLE_FILE, STRIP_POSITION_FILE};
+use crate::image_processor::ImageProcessor;
+use crate::progress_manager::ProgressManager;
+
+const FRONT_COVER: &str = "FrontCover";
+
+enum PageSource {
+    Archive(String),
+    File(PathBuf),
+}
+
+/// A page of the edited archive, with its `<Page>` details from ComicInfo.xml.
+struct EditedPage {
+    source: PageSource,
+    /// Index of the page before the edit; None for inserted pages.
+    original: Option<usize>,
+    page_type: String,
+    double_page: bool,
+}
+
+pub struct PageEditor;
+
+impl PageEditor {
+    pub fn delete_pages(
+        fm: &FileManager,
+        file_name: &str,
+        page_indexes: &[usize],
+    ) -> Result<usize, AppError> {
+        let doomed: BTreeSet<usize> = page_indexes.iter().copied().collect();
+
+        Self::edit(fm, file_name, |pages| {
+            Self::check_index(pages, doomed.last().copied())?;
+
+            let mut index = 0;
+            pages.retain(|_| {
+                let keep = !doomed.contains(&index);
+                index += 1;
+                keep
+            });
+            Ok(())
+        })
+    }
+
+    pub fn move_page(
+        fm: &FileManager,
+        file_name: &str,
+        from: usize,
+        to: usize,
+    ) -> Result<usize, AppError> {
+        Self::edit(fm, file_name, |pages| {
+            Self::check_index(pages, Some(from.max(to)))?;
+
+            let page = pages.remove(from);
+            pages.insert(to, page);
+            Ok(())
+        })
+    }
+
+    /// Inserts images from disk before the page at `position`, or at the end
+    /// when `position` equals the page count.
+    pub fn insert_pages(
+        fm: &FileManager,
+        file_name: &str,
+        position: usize,
+        image_paths: &[String],
+    ) -> Result<usize, AppError> {
+        for path in image_paths {
+            if !CbzViewer::is_image(path) {
+                return Err(AppError::unsupported_format(path.as_str()));
+            }
+            let data = fs::read(path).map_err(|e| AppError::fs(path, e))?;
+            ImageProcessor::decode(&data).map_err(|e| AppError::from_image(path.as_str(), e))?;
+        }
+
+        Self::edit(fm, file_name, |pages| {
+            if position > pages.len() {
+                return Err(AppError::invalid_input(format!(
+                    "Position {} out of range ({} pages)",
+                    position,
+                    pages.len()
+                )));
+            }
+
+            let inserted = image_paths.iter().map(|path| EditedPage {
+                source: PageSource::File(PathBuf::from(path)),
+                original: None,
+                page_type: String::new(),
+                double_page: false,
+            });
+            pages.splice(position..position, inserted);
+            Ok(())
+        })
+    }
+
+    /// Marks a page as the FrontCover in ComicInfo.xml, without moving it, and
+    /// refreshes the stored cover.
+    pub fn set_cover(
+        fm: &FileManager,
+        file_name: &str,
+        page_index: usize,
+    ) -> Result<usize, AppError> {
+        Self::edit(fm, file_name, |pages| {
+            Self::check_index(pages, Some(page_index))?;
+
+            for page in pages.iter_mut() {
+                if page.page_type.eq_ignore_ascii_case(FRONT_COVER) {
+                    page.page_type.clear();
+                }
+            }
+            pages[page_index].page_type = FRONT_COVER.to_string();
+            Ok(())
+        })
+    }
+
+    fn check_index(pages: &[EditedPage], index: Option<usize>) -> Result<(), AppError> {
+        match index {
+            Some(index) if index >= pages.len() => Err(AppError::invalid_input(format!(
+                "Page {} out of range ({} pages)",
+                index,
+                pages.len()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies an edit to the page list and writes the result to a new
+    /// archive, which then replaces the original in a single rename. Pages are
+    /// renamed after their new position, and ComicInfo.xml gets the new page
+    /// count and page details. Returns the new page count.
+    fn edit<F>(fm: &FileManager, file_name: &str, edit: F) -> Result<usize, AppError>
+    where
+        F: FnOnce(&mut Vec<EditedPage>) -> Result<(), AppError>,
+    {
+        let full_path = fm.get_full_path(file_name)?;
+        let cbz_path = full_path.to_string_lossy().to_string();
+
+        let page_info = CbzViewer::read_page_info(&cbz_path).unwrap_or_else(|e| {
+            warn!("Could not read page info for {}: {}", file_name, e);
+            Vec::new()
+        });
+        let mut pages: Vec<EditedPage> = CbzViewer::get_image_list(&cbz_path)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| {
+                let info = page_info.iter().find(|info| info.image == index);
+                EditedPage {
+                    source: PageSource::Archive(name),
+                    original: Some(index),
+                    page_type: info.map(|info| info.page_type.clone()).unwrap_or_default(),
+                    double_page: info.is_some_and(|info| info.double_page),
+                }
+            })
+            .collect();
+        let old_count = pages.len();
+
+        edit(&mut pages)?;
+        if pages.is_empty() {
+            return Err(AppError::invalid_input("A comic needs at least one page"));
+        }
+
+        let temp_path = full_path.with_extension(format!("{}.edit.tmp", Uuid::new_v4()));
+        if let Err(e) = Self::write(fm, file_name, &cbz_path, &temp_path, &pages) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        fs::rename(&temp_path, &full_path).map_err(|e| AppError::fs(&temp_path, e))?;
+
+        let mut new_indexes = vec![None; old_count];
+        for (index, page) in pages.iter().enumerate() {
+            if let Some(original) = page.original {
+                new_indexes[original] = Some(index);
+            }
+        }
+        Self::remap_sidecars(fm, file_name, &new_indexes, pages.len());
+
+        info!("Rewrote {} with {} pages", file_name, pages.len());
+        Ok(pages.len())
+    }
+
+    /// Brings the data stored by page index in line with rewritten pages: the
+    /// page table is rebuilt on next open, bookmarks and reading progress
+    /// follow their pages, crop boxes and the strip position are recomputed on
+    /// next open, and the stored page count and cover are refreshed.
+    /// `new_indexes` holds the new index of each old page, None for pages that
+    /// are gone. Failures are logged, since the archive itself has already
+    /// been replaced.
+    pub fn remap_sidecars(
+        fm: &FileManager,
+        file_name: &str,
+        new_indexes: &[Option<usize>],
+        page_count: usize,
+    ) {
+        // Pages keep their entry names across a reorder, so the page table is
+        // dropped rather than left to the archive stamp alone
+        if let Ok(folder) = fm.get_comic_folder(file_name) {
+            let page_table = folder.join(PAGE_TABLE_FILE);
+            if page_table.exists() {
+                if let Err(e) = fs::remove_file(&page_table) {
+                    warn!("Failed to remove {}: {}", page_table.display(), e);
+                }
+            }
+        }
+
+        let unchanged = new_indexes.len() == page_count
+            && new_indexes
+                .iter()
+                .enumerate()
+                .all(|(index, new_index)| *new_index == Some(index));
+
+        if !unchanged {
+            if let Ok(folder) = fm.get_comic_folder(file_name) {
+                for sidecar in [CROP_BOXES_FILE, STRIP_POSITION_FILE] {
+                    let path = folder.join(sidecar);
+                    if path.exists() {
+                        if let Err(e) = fs::remove_file(&path) {
+                            warn!("Failed to remove {}: {}", path.display(), e);
+                        }
+                    }
+                }
+            }
+            if let Err(e) = BookmarkManager::remap_pages(fm, file_name, new_indexes) {
+                warn!("Failed to move bookmarks of {}: {}", file_name, e);
+            }
+            if let Err(e) = ProgressManager::remap_pages(fm, file_name, new_indexes, page_count) {
+                warn!("Failed to move reading progress of {}: {}", file_name, e);
+            }
+        }
+
+        if let Err(e) = fm.set_page_count(file_name, page_count) {
+            warn!("Failed to store page count of {}: {}", file_name, e);
+        }
+        if let Err(e) = fm.rebuild_cover(file_name) {
+            warn!("Failed to rebuild cover of {}: {}", file_name, e);
+        }
+    }
+
+    fn write(
+        fm: &FileManager,
+        file_name: &str,
+        cbz_path: &str,
+        temp_path: &Path,
+        pages: &[EditedPage],
+    ) -> Result<(), AppError> {
+        let mut archive = CbzViewer::open_archive(cbz_path)?;
+        let file = File::create(temp_path).map_err(|e| AppError::fs(temp_path, e))?;
+        let mut writer = ZipWriter::new(file);
+        let zip_error = |e| AppError::archive_corrupt(cbz_path, e);
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        let mut comic_info = None;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(zip_error)?;
+            let name = entry.name().to_string();
+
+            if name.to_lowercase().ends_with("comicinfo.xml") {
+                let mut xml = String::new();
+                entry
+                    .read_to_string(&mut xml)
+                    .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+                comic_info = Some(xml);
+            } else if !entry.is_dir() && !CbzViewer::is_image(&name) {
+                writer.raw_copy_file(entry).map_err(zip_error)?;
+            }
+        }
+
+        let width = pages.len().to_string().len().max(3);
+        for (index, page) in pages.iter().enumerate() {
+            let (source_name, data) = match &page.source {
+                PageSource::Archive(name) => {
+                    let mut entry = archive.by_name(name).map_err(zip_error)?;
+                    let mut data = Vec::new();
+                    entry
+                        .read_to_end(&mut data)
+                        .map_err(|e| AppError::archive_corrupt(cbz_path, e))?;
+                    (name.clone(), data)
+                }
+                PageSource::File(path) => (
+                    path.to_string_lossy().to_string(),
+                    fs::read(path).map_err(|e| AppError::fs(path, e))?,
+                ),
+            };
+            let extension = Path::new(&source_name)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(str::to_lowercase)
+                .unwrap_or_else(|| "jpg".to_string());
+
+            writer
+                .start_file(
+                    format!("{:0width$}.{}", index + 1, extension, width = width),
+                    stored,
+                )
+                .map_err(zip_error)?;
+            writer
+                .write_all(&data)
+                .map_err(|e| AppError::fs(temp_path, e))?;
+        }
+
+        // Comics without a ComicInfo.xml get one when a page needs details
+        let needs_info = pages
+            .iter()
+            .any(|page| !page.page_type.is_empty() || page.double_page);
+        let xml = match comic_info {
+            Some(xml) => Some(xml),
+            None if needs_info => fm
+                .get_metadata(file_name)
+                .ok()
+                .map(|info| info.to_xml(None)),
+            None => None,
+        };
+
+        if let Some(mut xml) = xml {
+            ComicInfo::set_element(&mut xml, "PageCount", &pages.len().to_string());
+            let page_info: Vec<ComicPageInfo> = pages
+                .iter()
+                .enumerate()
+                .map(|(image, page)| ComicPageInfo {
+                    image,
+                    page_type: page.page_type.clone(),
+                    double_page: page.double_page,
+                })
+                .collect();
+            ComicInfo::set_pages(&mut xml, &page_info);
+
+            writer
+                .start_file("ComicInfo.xml", SimpleFileOptions::default())
+                .map_err(zip_error)?;
+            writer
+                .write_all(xml.as_bytes())
+                .map_err(|e| AppError::fs(temp_path, e))?;
+        }
+
+        let file = writer.finish().map_err(zip_error)?;
+        // The rename must never expose an archive that is not fully on disk
+        file.sync_all().map_err(|e| AppError::fs(temp_path, e))
+    }
+}
@@ -0,0 +1,348 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::cbz_viewer::{CbzViewer, ComicPageInfo, PageDimensions};
+use crate::comic_settings::{ComicSettings, ReadingDirection};
+use crate::errors::AppError;
+use crate::file_manager::{archive_stamp, FileManager, PAGE_TABLE_FILE};
+
+pub const DEFAULT_STRIP_SEGMENT_HEIGHT: u32 = 4096;
+/// Widest strip rendered, well past any screen, so a bad width cannot ask for
+/// an enormous image.
+pub const MAX_STRIP_WIDTH: u32 = 8192;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum PageHalf {
+    Left,
+    Right,
+}
+
+/// A page as presented to the reader. When spreads are split, one archive
+/// image becomes two virtual pages, each showing one half of the image.
+#[derive(Serialize, Clone, Debug)]
+pub struct VirtualPage {
+    pub index: usize,
+    pub image_index: usize,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub half: Option<PageHalf>,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpreadKind {
+    Cover,
+    Single,
+    Pair,
+    Wide,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Spread {
+    pub kind: SpreadKind,
+    /// Archive image indexes in screen order, left to right.
+    pub pages: Vec<usize>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SpreadPlan {
+    pub direction: ReadingDirection,
+    pub offset: usize,
+    pub spreads: Vec<Spread>,
+}
+
+/// A run of adjacent long-strip pages rendered as one image.
+#[derive(Serialize, Clone, Debug)]
+pub struct StripSegment {
+    pub index: usize,
+    /// Archive image indexes, top to bottom.
+    pub pages: Vec<usize>,
+    /// Distance from the top of the strip, in pixels at the strip width.
+    pub offset: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct StripLayout {
+    pub width: u32,
+    pub total_height: u32,
+    pub segments: Vec<StripSegment>,
+}
+
+/// Page sizes and ComicInfo page entries of an archive, cached next to the comic.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PageTable {
+    pub archive_size: u64,
+    pub archive_modified: u64,
+    pub pages: Vec<PageDimensions>,
+    pub page_info: Vec<ComicPageInfo>,
+}
+
+impl PageTable {
+    pub fn info_for(&self, image_index: usize) -> Option<&ComicPageInfo> {
+        self.page_info.iter().find(|info| info.image == image_index)
+    }
+
+    fn is_cover(&self, image_index: usize) -> bool {
+        match self
+            .page_info
+            .iter()
+            .find(|info| info.page_type == "FrontCover")
+        {
+            Some(info) => info.image == image_index,
+            None => image_index == 0,
+        }
+    }
+
+    pub fn is_spread(&self, image_index: usize, threshold: f32) -> bool {
+        if self
+            .info_for(image_index)
+            .is_some_and(|info| info.double_page)
+        {
+            return true;
+        }
+
+        self.pages.get(image_index).is_some_and(|page| {
+            page.height > 0 && page.width as f32 / page.height as f32 > threshold
+        })
+    }
+}
+
+pub struct PageLayout;
+
+impl PageLayout {
+    pub fn page_table(fm: &FileManager, cbz_path: &str) -> Result<PageTable, AppError> {
+        let full_path = fm.get_full_path(cbz_path)?;
+        let (archive_size, archive_modified) = archive_stamp(&full_path)?;
+
+        let cached: PageTable = fm
+            .read_sidecar(cbz_path, PAGE_TABLE_FILE)
+            .unwrap_or_default();
+        if cached.archive_size == archive_size
+            && cached.archive_modified == archive_modified
+            && !cached.pages.is_empty()
+        {
+            return Ok(cached);
+        }
+
+        let path_str = full_path.to_string_lossy();
+
+        let page_info = CbzViewer::read_page_info(&path_str).unwrap_or_else(|e| {
+            warn!("Could not read page info for {}: {}", cbz_path, e);
+            Vec::new()
+        });
+
+        let table = PageTable {
+            archive_size,
+            archive_modified,
+            pages: CbzViewer::get_page_dimensions(&path_str)?,
+            page_info,
+        };
+
+        if let Err(e) = fm.write_sidecar(cbz_path, PAGE_TABLE_FILE, &table) {
+            warn!("Failed to store page table for {}: {}", cbz_path, e);
+        }
+
+        Ok(table)
+    }
+
+    pub fn virtual_pages(
+        fm: &FileManager,
+        cbz_path: &str,
+        settings: &ComicSettings,
+    ) -> Result<Vec<VirtualPage>, AppError> {
+        let table = Self::page_table(fm, cbz_path)?;
+
+        let halves = match settings.reading_direction {
+            ReadingDirection::RightToLeft => [PageHalf::Right, PageHalf::Left],
+            _ => [PageHalf::Left, PageHalf::Right],
+        };
+
+        let mut pages = Vec::with_capacity(table.pages.len());
+
+        for (image_index, page) in table.pages.iter().enumerate() {
+            if settings.split_spreads && table.is_spread(image_index, settings.spread_threshold) {
+                for half in halves {
+                    let width = match half {
+                        PageHalf::Left => page.width / 2,
+                        PageHalf::Right => page.width - page.width / 2,
+                    };
+                    pages.push(VirtualPage {
+                        index: pages.len(),
+                        image_index,
+                        name: page.name.clone(),
+                        width,
+                        height: page.height,
+                        half: Some(half),
+                    });
+                }
+            } else {
+                pages.push(VirtualPage {
+                    index: pages.len(),
+                    image_index,
+                    name: page.name.clone(),
+                    width: page.width,
+                    height: page.height,
+                    half: None,
+                });
+            }
+        }
+
+        Ok(pages)
+    }
+
+    pub fn page_count(
+        fm: &FileManager,
+        cbz_path: &str,
+        settings: &ComicSettings,
+    ) -> Result<usize, AppError> {
+        if !settings.split_spreads {
+            let full_path = fm.get_full_path(cbz_path)?;
+            return Ok(CbzViewer::get_image_list(&full_path.to_string_lossy())?.len());
+        }
+
+        Ok(Self::virtual_pages(fm, cbz_path, settings)?.len())
+    }
+
+    /// Maps a page index from the reader to the archive image and half to show.
+    pub fn resolve(
+        fm: &FileManager,
+        cbz_path: &str,
+        settings: &ComicSettings,
+        index: usize,
+    ) -> Result<(usize, Option<PageHalf>), AppError> {
+        if !settings.split_spreads {
+            return Ok((index, None));
+        }
+
+        let pages = Self::virtual_pages(fm, cbz_path, settings)?;
+        let page = pages.get(index).ok_or_else(|| {
+            AppError::invalid_input(format!(
+                "Page {} out of range ({} pages)",
+                index,
+                pages.len()
+            ))
+        })?;
+
+        Ok((page.image_index, page.half))
+    }
+
+    /// Groups the archive pages into the spreads shown in two-page mode. The
+    /// cover and wide pages stand alone; the rest pair up in reading order, with
+    /// an odd `offset` shifting the pairing by one page.
+    pub fn spread_plan(
+        fm: &FileManager,
+        cbz_path: &str,
+        settings: &ComicSettings,
+        offset: usize,
+    ) -> Result<SpreadPlan, AppError> {
+        let table = Self::page_table(fm, cbz_path)?;
+        let is_wide = |index: usize| table.is_spread(index, settings.spread_threshold);
+
+        let mut spreads = Vec::new();
+        let mut push = |kind: SpreadKind, mut pages: Vec<usize>| {
+            if settings.reading_direction == ReadingDirection::RightToLeft {
+                pages.reverse();
+            }
+            spreads.push(Spread { kind, pages });
+        };
+
+        let mut index = 0;
+        let mut shift_pending = offset % 2 == 1;
+
+        while index < table.pages.len() {
+            if is_wide(index) {
+                push(SpreadKind::Wide, vec![index]);
+                index += 1;
+                continue;
+            }
+
+            if table.is_cover(index) {
+                push(SpreadKind::Cover, vec![index]);
+                index += 1;
+                continue;
+            }
+
+            if shift_pending {
+                shift_pending = false;
+                push(SpreadKind::Single, vec![index]);
+                index += 1;
+                continue;
+            }
+
+            let next = index + 1;
+            if next < table.pages.len() && !is_wide(next) && !table.is_cover(next) {
+                push(SpreadKind::Pair, vec![index, next]);
+                index += 2;
+            } else {
+                push(SpreadKind::Single, vec![index]);
+                index += 1;
+            }
+        }
+
+        Ok(SpreadPlan {
+            direction: settings.reading_direction,
+            offset,
+            spreads,
+        })
+    }
+
+    /// Lays out every page at a common `width` for long-strip reading. With
+    /// stitching, adjacent pages are grouped into segments of at most
+    /// `max_segment_height` pixels; without it every page is its own segment.
+    /// Pages narrower than the strip keep their size.
+    pub fn strip_layout(
+        fm: &FileManager,
+        cbz_path: &str,
+        width: u32,
+        stitch: bool,
+        max_segment_height: u32,
+    ) -> Result<StripLayout, AppError> {
+        if !(1..=MAX_STRIP_WIDTH).contains(&width) {
+            return Err(AppError::invalid_input(format!(
+                "Strip width must be between 1 and {}, got {}",
+                MAX_STRIP_WIDTH, width
+            )));
+        }
+
+        let table = Self::page_table(fm, cbz_path)?;
+        let mut segments: Vec<StripSegment> = Vec::new();
+        let mut offset: u32 = 0;
+
+        for (image_index, page) in table.pages.iter().enumerate() {
+            let height = if page.width == 0 {
+                0
+            } else if page.width <= width {
+                page.height
+            } else {
+                (page.height as u64 * width as u64 / page.width as u64) as u32
+            };
+
+            match segments.last_mut() {
+                Some(segment)
+                    if stitch && segment.height.saturating_add(height) <= max_segment_height =>
+                {
+                    segment.pages.push(image_index);
+                    segment.height += height;
+                }
+                _ => segments.push(StripSegment {
+                    index: segments.len(),
+                    pages: vec![image_index],
+                    offset,
+                    height,
+                }),
+            }
+
+            offset = offset.saturating_add(height);
+        }
+
+        Ok(StripLayout {
+            width,
+            total_height: offset,
+            segments,
+        })
+    }
+}
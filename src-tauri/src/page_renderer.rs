@@ -0,0 +1,163 @@
+use log::warn;
+
+use crate::cbz_viewer::CbzViewer;
+use crate::comic_settings::{CropBox, CropBoxCache};
+use crate::errors::AppError;
+use crate::file_manager::{FileManager, CROP_BOXES_FILE};
+use crate::image_processor::{FitMode, ImageProcessor};
+use crate::page_cache::PageCache;
+use crate::page_layout::{PageHalf, StripSegment};
+
+#[derive(Clone, Debug, Default, Hash)]
+pub struct PageRequest {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit_mode: FitMode,
+    pub crop: bool,
+    pub crop_tolerance: u8,
+    pub half: Option<PageHalf>,
+}
+
+impl PageRequest {
+    fn needs_processing(&self) -> bool {
+        self.width.is_some() || self.height.is_some() || self.crop || self.half.is_some()
+    }
+}
+
+pub struct PageRenderer;
+
+impl PageRenderer {
+    /// Loads a page as a data URL, cropping and resizing it when requested.
+    /// Processed pages are served from the page cache when possible.
+    pub fn render(
+        fm: &FileManager,
+        cache: &PageCache,
+        cbz_path: &str,
+        image_index: usize,
+        request: &PageRequest,
+    ) -> Result<String, AppError> {
+        let full_path = fm.get_full_path(cbz_path)?;
+        let path_str = full_path.to_string_lossy();
+
+        if !request.needs_processing() {
+            return CbzViewer::load_image_by_index(&path_str, image_index);
+        }
+
+        let key = PageCache::cache_key(&full_path, &(image_index, request))?;
+        if let Some((image_data, extension)) = cache.get(&key) {
+            return Ok(ImageProcessor::to_data_url(&image_data, extension));
+        }
+
+        let (image_name, image_data) = CbzViewer::read_image_bytes(&path_str, image_index)?;
+        let mut image = ImageProcessor::decode(&image_data)
+            .map_err(|e| AppError::from_image(format!("{}/{}", path_str, image_name), e))?;
+
+        if request.crop {
+            if let Some(crop_box) = Self::crop_box(fm, cbz_path, image_index, &image, request) {
+                image = ImageProcessor::crop(image, &crop_box);
+            }
+        }
+
+        if let Some(half) = request.half {
+            image = ImageProcessor::split(image, half);
+        }
+
+        let image = ImageProcessor::resize(image, request.width, request.height, request.fit_mode);
+        let (encoded, extension) = ImageProcessor::encode(&image, &image_name)?;
+
+        if let Err(e) = cache
+            .put(&key, &encoded, extension)
+            .and_then(|_| cache.prune())
+        {
+            warn!("Failed to update page cache: {}", e);
+        }
+
+        Ok(ImageProcessor::to_data_url(&encoded, extension))
+    }
+
+    fn crop_box(
+        fm: &FileManager,
+        cbz_path: &str,
+        image_index: usize,
+        image: &image::DynamicImage,
+        request: &PageRequest,
+    ) -> Option<CropBox> {
+        if let Some(crop_box) = Self::crop_boxes(fm, cbz_path, request)
+            .boxes
+            .get(&image_index)
+        {
+            return *crop_box;
+        }
+
+        let crop_box = ImageProcessor::detect_crop_box(image, request.crop_tolerance);
+
+        // Pages load in parallel, so the cache is read again under the lock
+        // to keep boxes other pages stored in the meantime
+        let _lock = fm.lock_sidecars();
+        let mut crop_boxes = Self::crop_boxes(fm, cbz_path, request);
+        crop_boxes.boxes.insert(image_index, crop_box);
+        if let Err(e) = fm.write_sidecar(cbz_path, CROP_BOXES_FILE, &crop_boxes) {
+            warn!("Failed to store crop box for {}: {}", cbz_path, e);
+        }
+
+        crop_box
+    }
+
+    /// The stored crop boxes, or none when they were detected with another
+    /// tolerance or the cache cannot be read.
+    fn crop_boxes(fm: &FileManager, cbz_path: &str, request: &PageRequest) -> CropBoxCache {
+        let crop_boxes = fm
+            .read_sidecar::<CropBoxCache>(cbz_path, CROP_BOXES_FILE)
+            .unwrap_or_else(|e| {
+                warn!("Ignoring unreadable crop boxes of {}: {}", cbz_path, e);
+                CropBoxCache::default()
+            });
+
+        if crop_boxes.tolerance == request.crop_tolerance {
+            crop_boxes
+        } else {
+            CropBoxCache {
+                tolerance: request.crop_tolerance,
+                ..Default::default()
+            }
+        }
+    }
+
+    /// Renders a long-strip segment as one stitched image at the strip width.
+    pub fn render_strip_segment(
+        fm: &FileManager,
+        cache: &PageCache,
+        cbz_path: &str,
+        segment: &StripSegment,
+        width: u32,
+    ) -> Result<String, AppError> {
+        let full_path = fm.get_full_path(cbz_path)?;
+        let path_str = full_path.to_string_lossy();
+
+        let key = PageCache::cache_key(&full_path, &("strip", &segment.pages, width))?;
+        if let Some((image_data, extension)) = cache.get(&key) {
+            return Ok(ImageProcessor::to_data_url(&image_data, extension));
+        }
+
+        let mut images = Vec::with_capacity(segment.pages.len());
+        for &image_index in &segment.pages {
+            let (image_name, image_data) = CbzViewer::read_image_bytes(&path_str, image_index)?;
+            images
+                .push(ImageProcessor::decode(&image_data).map_err(|e| {
+                    AppError::from_image(format!("{}/{}", path_str, image_name), e)
+                })?);
+        }
+
+        let strip = ImageProcessor::stitch_vertical(images, width);
+        let (encoded, extension) = ImageProcessor::encode(&strip, "segment.jpg")?;
+
+        if let Err(e) = cache
+            .put(&key, &encoded, extension)
+            .and_then(|_| cache.prune())
+        {
+            warn!("Failed to update page cache: {}", e);
+        }
+
+        Ok(ImageProcessor::to_data_url(&encoded, extension))
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+use crate::cbz_viewer::CbzViewer;
+use crate::comic_settings::{ComicSettings, ReadingDirection, ViewPreferences};
+use crate::config_manager::Config;
+use crate::errors::AppError;
+use crate::file_manager::{FileManager, SETTINGS_FILE};
+
+pub struct PreferencesManager {
+    pub series_path: PathBuf,
+}
+
+impl PreferencesManager {
+    pub fn new(app: &AppHandle) -> Result<Self, AppError> {
+        let data_dir = app.path().app_data_dir()?;
+        fs::create_dir_all(&data_dir).map_err(|e| AppError::fs(&data_dir, e))?;
+
+        Ok(Self {
+            series_path: data_dir.join("series_preferences.json"),
+        })
+    }
+
+    fn load_series_map(&self) -> Result<BTreeMap<String, ViewPreferences>, AppError> {
+        if !self.series_path.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let data = fs::read_to_string(&self.series_path)
+            .map_err(|e| AppError::fs(&self.series_path, e))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    fn series_key(series: &str) -> String {
+        series.trim().to_lowercase()
+    }
+
+    pub fn get_series(&self, series: &str) -> Result<ViewPreferences, AppError> {
+        Ok(self
+            .load_series_map()?
+            .remove(&Self::series_key(series))
+            .unwrap_or_default())
+    }
+
+    pub fn save_series(&self, series: &str, preferences: &ViewPreferences) -> Result<(), AppError> {
+        let mut series_map = self.load_series_map()?;
+
+        if *preferences == ViewPreferences::default() {
+            series_map.remove(&Self::series_key(series));
+        } else {
+            series_map.insert(Self::series_key(series), preferences.clone());
+        }
+
+        let data = serde_json::to_string_pretty(&series_map)?;
+        fs::write(&self.series_path, data).map_err(|e| AppError::fs(&self.series_path, e))
+    }
+
+    pub fn get_comic(fm: &FileManager, file_name: &str) -> Result<ViewPreferences, AppError> {
+        fm.read_sidecar(file_name, SETTINGS_FILE)
+    }
+
+    pub fn save_comic(
+        fm: &FileManager,
+        file_name: &str,
+        preferences: &ViewPreferences,
+    ) -> Result<(), AppError> {
+        fm.write_sidecar(file_name, SETTINGS_FILE, preferences)
+    }
+
+    /// Resolves the settings for a comic. Later levels win: built-in defaults,
+    /// the global config, the comic's metadata, its series, then the comic itself.
+    pub fn resolve(
+        &self,
+        fm: &FileManager,
+        file_name: &str,
+        config: &Config,
+    ) -> Result<ComicSettings, AppError> {
+        let mut settings = ComicSettings::default();
+        config.reader.default_view.apply_to(&mut settings);
+
+        let comic_info = fm.get_metadata(file_name).ok();
+
+        if let Some(info) = &comic_info {
+            // Metadata files written before the Manga field existed lack it,
+            // so the archive's own ComicInfo.xml is asked instead
+            let manga = if info.manga.trim().is_empty() {
+                fm.get_full_path(file_name)
+                    .ok()
+                    .and_then(|path| CbzViewer::read_comic_info(&path.to_string_lossy()).ok())
+                    .map(|archive_info| archive_info.manga)
+                    .unwrap_or_default()
+            } else {
+                info.manga.clone()
+            };
+            if manga.trim().eq_ignore_ascii_case("YesAndRightToLeft") {
+                settings.reading_direction = ReadingDirection::RightToLeft;
+            }
+
+            if !info.series.trim().is_empty() {
+                self.get_series(&info.series)?.apply_to(&mut settings);
+            }
+        }
+
+        Self::get_comic(fm, file_name)?.apply_to(&mut settings);
+        Ok(settings)
+    }
+}
@@ -0,0 +1,315 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+use crate::errors::{AppError, ItemError};
+use crate::file_manager::{FileManager, PROGRESS_FILE};
+use crate::library::Library;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadStatus {
+    #[default]
+    Unread,
+    InProgress,
+    Read,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ReadingState {
+    pub status: ReadStatus,
+    pub current_page: usize,
+    pub furthest_page: usize,
+    pub page_count: usize,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub last_read_at: Option<DateTime<Utc>>,
+    pub read_count: u32,
+}
+
+impl ReadingState {
+    /// A comic is in progress between opening it and reaching its last page.
+    pub fn is_in_progress(&self) -> bool {
+        match (self.started_at, self.finished_at) {
+            (Some(started), Some(finished)) => started > finished,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+
+    fn is_last_page(&self, page: usize) -> bool {
+        self.page_count > 0 && page + 1 >= self.page_count
+    }
+
+    fn refresh_status(&mut self) {
+        self.status = if self.is_in_progress() {
+            ReadStatus::InProgress
+        } else if self.finished_at.is_some() {
+            ReadStatus::Read
+        } else {
+            ReadStatus::Unread
+        };
+    }
+
+    /// Records that the reader is on `page`. Moving away from the last page of
+    /// a finished comic starts a new read-through, which counts again once the
+    /// last page is reached.
+    fn go_to_page(&mut self, page: usize, page_count: usize, now: DateTime<Utc>) {
+        self.page_count = page_count;
+
+        let finished = !self.is_in_progress() && self.finished_at.is_some();
+        if self.started_at.is_none() || (finished && !self.is_last_page(page)) {
+            self.started_at = Some(now);
+            self.furthest_page = page;
+        }
+
+        self.current_page = page;
+        self.furthest_page = self.furthest_page.max(page);
+        self.last_read_at = Some(now);
+
+        if self.is_last_page(page) && self.is_in_progress() {
+            self.finished_at = Some(now);
+            self.read_count += 1;
+        }
+        self.refresh_status();
+    }
+
+    /// Applies an explicit status. Reading history (read count, timestamps of
+    /// earlier read-throughs) is kept where the status allows it, but a comic
+    /// marked unread loses its last read time along with its progress.
+    fn set_status(&mut self, status: ReadStatus, now: DateTime<Utc>) {
+        match status {
+            ReadStatus::Unread => {
+                self.started_at = None;
+                self.finished_at = None;
+                self.last_read_at = None;
+                self.current_page = 0;
+                self.furthest_page = 0;
+            }
+            ReadStatus::InProgress => {
+                if !self.is_in_progress() {
+                    self.started_at = Some(now);
+                }
+                self.last_read_at = Some(now);
+            }
+            ReadStatus::Read => {
+                if self.status != ReadStatus::Read {
+                    self.started_at.get_or_insert(now);
+                    self.finished_at = Some(now);
+                    self.read_count += 1;
+                    if self.page_count > 0 {
+                        self.current_page = self.page_count - 1;
+                        self.furthest_page = self.page_count - 1;
+                    }
+                }
+                self.last_read_at = Some(now);
+            }
+        }
+
+        self.refresh_status();
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ContinueReadingEntry {
+    pub file_name: String,
+    pub state: ReadingState,
+}
+
+pub struct ProgressManager;
+
+impl ProgressManager {
+    pub fn get_state(fm: &FileManager, file_name: &str) -> Result<ReadingState, AppError> {
+        let mut state: ReadingState = fm.read_sidecar(file_name, PROGRESS_FILE)?;
+        state.refresh_status();
+        Ok(state)
+    }
+
+    pub fn set_status(
+        fm: &FileManager,
+        file_name: &str,
+        status: ReadStatus,
+    ) -> Result<ReadingState, AppError> {
+        let mut state = Self::get_state(fm, file_name)?;
+        state.set_status(status, Utc::now());
+        fm.write_sidecar(file_name, PROGRESS_FILE, &state)?;
+        Ok(state)
+    }
+
+    /// Sets the status of several comics, returning the ones that failed with their error.
+    pub fn set_status_bulk(
+        fm: &FileManager,
+        file_names: &[String],
+        status: ReadStatus,
+    ) -> Vec<ItemError> {
+        file_names
+            .iter()
+            .filter_map(|file_name| {
+                Self::set_status(fm, file_name, status)
+                    .err()
+                    .map(|e| ItemError::new(file_name.as_str(), &e))
+            })
+            .collect()
+    }
+
+    pub fn update_progress(
+        fm: &FileManager,
+        file_name: &str,
+        page: usize,
+        page_count: usize,
+    ) -> Result<ReadingState, AppError> {
+        let mut state = Self::get_state(fm, file_name)?;
+        state.go_to_page(page, page_count, Utc::now());
+        fm.write_sidecar(file_name, PROGRESS_FILE, &state)?;
+        Ok(state)
+    }
+
+    /// Follows the pages of a comic after they were rewritten. The current
+    /// page moves with its image, or to the closest earlier page still there
+    /// when it was removed; the furthest page becomes the furthest new index
+    /// among the pages read up to it. `new_indexes` holds the new index of
+    /// each old page.
+    pub fn remap_pages(
+        fm: &FileManager,
+        file_name: &str,
+        new_indexes: &[Option<usize>],
+        page_count: usize,
+    ) -> Result<(), AppError> {
+        let mut state = Self::get_state(fm, file_name)?;
+        if state.started_at.is_none() && state.finished_at.is_none() {
+            return Ok(());
+        }
+
+        let read_up_to = |page: usize| new_indexes.iter().take(page + 1).flatten().copied();
+        state.current_page = new_indexes
+            .get(state.current_page)
+            .copied()
+            .flatten()
+            .or_else(|| {
+                new_indexes
+                    .iter()
+                    .take(state.current_page)
+                    .rev()
+                    .find_map(|index| *index)
+            })
+            .unwrap_or(0);
+        state.furthest_page = read_up_to(state.furthest_page)
+            .max()
+            .unwrap_or(0)
+            .max(state.current_page);
+        state.page_count = page_count;
+
+        fm.write_sidecar(file_name, PROGRESS_FILE, &state)
+    }
+
+    /// Comics that were opened but not finished, most recently read first.
+    pub fn continue_reading(library: &Library) -> Result<Vec<ContinueReadingEntry>, AppError> {
+        let mut entries = Vec::new();
+
+        for file_name in library.list_files() {
+            let state = Self::get_state(&library.files, &file_name)?;
+            if state.is_in_progress() {
+                entries.push(ContinueReadingEntry { file_name, state });
+            }
+        }
+
+        entries.sort_by_key(|entry| Reverse(entry.state.last_read_at));
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReadStatus, ReadingState};
+    use chrono::{DateTime, Duration, Utc};
+
+    const PAGES: usize = 10;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + Duration::minutes(minutes)
+    }
+
+    fn finished() -> ReadingState {
+        let mut state = ReadingState::default();
+        state.go_to_page(0, PAGES, at(0));
+        state.go_to_page(PAGES - 1, PAGES, at(1));
+        state
+    }
+
+    #[test]
+    fn opening_a_comic_starts_reading() {
+        let mut state = ReadingState::default();
+        state.go_to_page(3, PAGES, at(0));
+
+        assert_eq!(state.status, ReadStatus::InProgress);
+        assert_eq!(state.started_at, Some(at(0)));
+        assert_eq!(state.furthest_page, 3);
+    }
+
+    #[test]
+    fn reaching_the_last_page_finishes() {
+        let state = finished();
+
+        assert_eq!(state.status, ReadStatus::Read);
+        assert_eq!(state.finished_at, Some(at(1)));
+        assert_eq!(state.read_count, 1);
+    }
+
+    #[test]
+    fn staying_on_the_last_page_does_not_count_again() {
+        let mut state = finished();
+        state.go_to_page(PAGES - 1, PAGES, at(2));
+
+        assert_eq!(state.status, ReadStatus::Read);
+        assert_eq!(state.read_count, 1);
+    }
+
+    #[test]
+    fn leaving_the_last_page_of_a_finished_comic_restarts() {
+        let mut state = finished();
+        state.go_to_page(4, PAGES, at(2));
+
+        assert_eq!(state.status, ReadStatus::InProgress);
+        assert_eq!(state.started_at, Some(at(2)));
+        assert_eq!(state.current_page, 4);
+        assert_eq!(state.furthest_page, 4);
+        assert_eq!(state.read_count, 1);
+    }
+
+    #[test]
+    fn finishing_a_reread_counts_again() {
+        let mut state = finished();
+        state.go_to_page(0, PAGES, at(2));
+        state.go_to_page(PAGES - 1, PAGES, at(3));
+
+        assert_eq!(state.status, ReadStatus::Read);
+        assert_eq!(state.finished_at, Some(at(3)));
+        assert_eq!(state.read_count, 2);
+    }
+
+    #[test]
+    fn marking_unread_clears_last_read() {
+        let mut state = finished();
+        state.set_status(ReadStatus::Unread, at(2));
+
+        assert_eq!(state.status, ReadStatus::Unread);
+        assert_eq!(state.last_read_at, None);
+        assert_eq!(state.current_page, 0);
+        assert_eq!(state.read_count, 1);
+    }
+
+    #[test]
+    fn marking_read_sets_last_read() {
+        let mut state = ReadingState {
+            page_count: PAGES,
+            ..ReadingState::default()
+        };
+        state.set_status(ReadStatus::Read, at(2));
+
+        assert_eq!(state.status, ReadStatus::Read);
+        assert_eq!(state.last_read_at, Some(at(2)));
+        assert_eq!(state.current_page, PAGES - 1);
+        assert_eq!(state.read_count, 1);
+    }
+}
@@ -0,0 +1,407 @@
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::cbz_viewer::{CbzViewer, ComicInfo, ComicPageInfo};
+use crate::converter::StagingDir;
+use crate::duplicate_finder::{DuplicateFinder, Fingerprint};
+use crate::errors::AppError;
+use crate::file_manager::UNKNOWN_WRITER;
+use crate::library::Library;
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct MergeOptions {
+    /// Title of the merged comic; the first comic's title when empty.
+    pub title: String,
+    pub volume: String,
+    /// Keeps each comic's pages in a subfolder of its own.
+    pub chapter_folders: bool,
+}
+
+/// A page to copy into a new archive.
+struct PlannedPage {
+    cbz_path: String,
+    entry_name: String,
+    target_name: String,
+    info: ComicPageInfo,
+}
+
+pub struct VolumeBuilder;
+
+impl VolumeBuilder {
+    /// Concatenates comics, in the given order, into a new library comic with
+    /// combined metadata. The source comics are left as they are. Returns the
+    /// new file name.
+    pub fn merge(
+        library: &Library,
+        file_names: &[String],
+        options: &MergeOptions,
+    ) -> Result<String, AppError> {
+        if file_names.len() < 2 {
+            return Err(AppError::invalid_input("Pick at least two comics to merge"));
+        }
+
+        let mut chapters = Vec::with_capacity(file_names.len());
+        for file_name in file_names {
+            let cbz_path = library
+                .files
+                .get_full_path(file_name)?
+                .to_string_lossy()
+                .to_string();
+            let images = CbzViewer::get_image_list(&cbz_path)?;
+            let page_info = Self::page_info(&cbz_path);
+            let metadata = library.metadata(file_name);
+            chapters.push((file_name, cbz_path, images, page_info, metadata));
+        }
+
+        let total: usize = chapters
+            .iter()
+            .map(|(_, _, images, _, _)| images.len())
+            .sum();
+        let page_width = Self::digits(total);
+        let chapter_width = Self::digits(chapters.len());
+
+        let mut pages = Vec::with_capacity(total);
+        for (chapter, (file_name, cbz_path, images, page_info, metadata)) in
+            chapters.iter().enumerate()
+        {
+            let folder = if options.chapter_folders {
+                let title = metadata
+                    .as_ref()
+                    .map(|info| info.title.clone())
+                    .filter(|title| !title.trim().is_empty())
+                    .unwrap_or_else(|| Self::stem(file_name));
+                format!(
+                    "{:0width$} - {}/",
+                    chapter + 1,
+                    Self::folder_name(&title),
+                    width = chapter_width
+                )
+            } else {
+                String::new()
+            };
+
+            for (index, name) in images.iter().enumerate() {
+                let mut info = Self::info_for(page_info, index);
+                // Only the first chapter keeps its cover marked as the cover
+                if chapter > 0 && info.page_type.eq_ignore_ascii_case("FrontCover") {
+                    info.page_type.clear();
+                }
+                info.image = pages.len();
+
+                pages.push(PlannedPage {
+                    cbz_path: cbz_path.clone(),
+                    entry_name: name.clone(),
+                    target_name: format!(
+                        "{}{:0width$}.{}",
+                        folder,
+                        pages.len() + 1,
+                        Self::extension(name),
+                        width = page_width
+                    ),
+                    info,
+                });
+            }
+        }
+
+        let metadata: Vec<ComicInfo> = chapters
+            .iter()
+            .filter_map(|(_, _, _, _, metadata)| metadata.clone())
+            .collect();
+        let comic_info = Self::combine(&metadata, options, pages.len());
+        let file_stem = Self::folder_name(&comic_info.title);
+
+        let staging = StagingDir::new()?;
+        let merged = staging.path().join(format!("{}.cbz", file_stem));
+        Self::write_archive(&merged, &pages, &comic_info)?;
+
+        let (file_name, _) = library.add_file(&merged.to_string_lossy())?;
+        info!("Merged {} comics into {}", file_names.len(), file_name);
+        Ok(file_name)
+    }
+
+    /// Cuts a comic before each of the given page indexes, adding every part
+    /// to the library as a comic of its own. Either every part is added or
+    /// none is. The original is left as it is. Returns the new file names.
+    pub fn split(
+        library: &Library,
+        file_name: &str,
+        page_indexes: &[usize],
+    ) -> Result<Vec<String>, AppError> {
+        let cbz_path = library
+            .files
+            .get_full_path(file_name)?
+            .to_string_lossy()
+            .to_string();
+        let images = CbzViewer::get_image_list(&cbz_path)?;
+        let page_info = Self::page_info(&cbz_path);
+
+        let cuts: BTreeSet<usize> = page_indexes.iter().copied().collect();
+        if cuts.is_empty() {
+            return Err(AppError::invalid_input(
+                "Pick at least one page to split at",
+            ));
+        }
+        if let Some(&cut) = cuts.iter().find(|&&cut| cut == 0 || cut >= images.len()) {
+            return Err(AppError::invalid_input(format!(
+                "Cannot split before page {} ({} pages)",
+                cut,
+                images.len()
+            )));
+        }
+
+        let mut bounds = vec![0];
+        bounds.extend(cuts);
+        bounds.push(images.len());
+
+        let metadata = library.metadata(file_name).unwrap_or_else(|| ComicInfo {
+            title: Self::stem(file_name),
+            series: String::new(),
+            number: String::new(),
+            volume: String::new(),
+            summary: String::new(),
+            year: String::new(),
+            month: String::new(),
+            day: String::new(),
+            writer: String::new(),
+            publisher: String::new(),
+            page_count: String::new(),
+            manga: String::new(),
+        });
+        let stem = Self::stem(file_name);
+        let staging = StagingDir::new()?;
+        let mut part_paths = Vec::with_capacity(bounds.len() - 1);
+
+        for (part, range) in bounds.windows(2).enumerate() {
+            let (start, end) = (range[0], range[1]);
+            let width = Self::digits(end - start);
+
+            let pages: Vec<PlannedPage> = (start..end)
+                .map(|index| {
+                    let mut info = Self::info_for(&page_info, index);
+                    info.image = index - start;
+                    PlannedPage {
+                        cbz_path: cbz_path.clone(),
+                        entry_name: images[index].clone(),
+                        target_name: format!(
+                            "{:0width$}.{}",
+                            index - start + 1,
+                            Self::extension(&images[index]),
+                            width = width
+                        ),
+                        info,
+                    }
+                })
+                .collect();
+
+            let comic_info = ComicInfo {
+                title: format!("{} (Part {})", metadata.title, part + 1),
+                page_count: pages.len().to_string(),
+                ..metadata.clone()
+            };
+            let part_path = staging
+                .path()
+                .join(format!("{} - Part {}.cbz", stem, part + 1));
+            Self::write_archive(&part_path, &pages, &comic_info)?;
+            part_paths.push(part_path.to_string_lossy().to_string());
+        }
+
+        // A part already in the library fails the split before anything is added
+        let library_fingerprints = library.fingerprints();
+        for part_path in &part_paths {
+            let fingerprint = Fingerprint::compute(part_path)?;
+            if let Some(existing) =
+                DuplicateFinder::matches(&fingerprint, &library_fingerprints).existing
+            {
+                return Err(AppError::Duplicate {
+                    path: part_path.clone(),
+                    existing,
+                });
+            }
+        }
+
+        let mut file_names = Vec::with_capacity(part_paths.len());
+        for part_path in &part_paths {
+            match library.add_file(part_path) {
+                Ok((part_name, _)) => file_names.push(part_name),
+                Err(e) => {
+                    for part_name in &file_names {
+                        if let Err(e) = library.delete_file(part_name) {
+                            warn!("Failed to remove {} after a failed split: {}", part_name, e);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        info!("Split {} into {} parts", file_name, file_names.len());
+        Ok(file_names)
+    }
+
+    /// Combines chapter metadata: shared values are kept, writers are listed
+    /// once each and the summaries are joined.
+    fn combine(metadata: &[ComicInfo], options: &MergeOptions, page_count: usize) -> ComicInfo {
+        let first = |field: fn(&ComicInfo) -> &String| {
+            metadata
+                .iter()
+                .map(field)
+                .find(|value| !value.trim().is_empty())
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        let mut writers: Vec<&str> = Vec::new();
+        for info in metadata {
+            for writer in info.writer.split(',').map(str::trim) {
+                if !writer.is_empty() && writer != UNKNOWN_WRITER && !writers.contains(&writer) {
+                    writers.push(writer);
+                }
+            }
+        }
+
+        let summaries: Vec<&str> = metadata
+            .iter()
+            .map(|info| info.summary.trim())
+            .filter(|summary| !summary.is_empty())
+            .collect();
+
+        let title = if options.title.trim().is_empty() {
+            first(|info| &info.title)
+        } else {
+            options.title.trim().to_string()
+        };
+
+        ComicInfo {
+            title: if title.is_empty() {
+                "Merged".to_string()
+            } else {
+                title
+            },
+            series: first(|info| &info.series),
+            number: String::new(),
+            volume: options.volume.clone(),
+            summary: summaries.join("\n\n"),
+            year: first(|info| &info.year),
+            month: first(|info| &info.month),
+            day: first(|info| &info.day),
+            writer: writers.join(", "),
+            publisher: first(|info| &info.publisher),
+            page_count: page_count.to_string(),
+            manga: first(|info| &info.manga),
+        }
+    }
+
+    fn write_archive(
+        destination: &Path,
+        pages: &[PlannedPage],
+        comic_info: &ComicInfo,
+    ) -> Result<(), AppError> {
+        let file = File::create(destination).map_err(|e| AppError::fs(destination, e))?;
+        let mut writer = ZipWriter::new(file);
+        let zip_error = |e| AppError::archive_corrupt(destination.display().to_string(), e);
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        // Pages come from a handful of archives, so each is opened once
+        let mut open: Option<(String, zip::ZipArchive<std::io::BufReader<File>>)> = None;
+        for page in pages {
+            if open.as_ref().is_none_or(|(path, _)| *path != page.cbz_path) {
+                open = Some((
+                    page.cbz_path.clone(),
+                    CbzViewer::open_archive(&page.cbz_path)?,
+                ));
+            }
+            let Some((_, archive)) = open.as_mut() else {
+                continue;
+            };
+
+            let mut entry = archive
+                .by_name(&page.entry_name)
+                .map_err(|e| AppError::archive_corrupt(&page.cbz_path, e))?;
+            let mut data = Vec::new();
+            entry
+                .read_to_end(&mut data)
+                .map_err(|e| AppError::archive_corrupt(&page.cbz_path, e))?;
+
+            writer
+                .start_file(page.target_name.as_str(), stored)
+                .map_err(zip_error)?;
+            writer
+                .write_all(&data)
+                .map_err(|e| AppError::fs(destination, e))?;
+        }
+
+        let mut xml = comic_info.to_xml(None);
+        let page_info: Vec<ComicPageInfo> = pages.iter().map(|page| page.info.clone()).collect();
+        ComicInfo::set_pages(&mut xml, &page_info);
+
+        writer
+            .start_file("ComicInfo.xml", SimpleFileOptions::default())
+            .map_err(zip_error)?;
+        writer
+            .write_all(xml.as_bytes())
+            .map_err(|e| AppError::fs(destination, e))?;
+        writer.finish().map_err(zip_error)?;
+        Ok(())
+    }
+
+    fn page_info(cbz_path: &str) -> Vec<ComicPageInfo> {
+        CbzViewer::read_page_info(cbz_path).unwrap_or_else(|e| {
+            warn!("Could not read page info for {}: {}", cbz_path, e);
+            Vec::new()
+        })
+    }
+
+    fn info_for(page_info: &[ComicPageInfo], index: usize) -> ComicPageInfo {
+        page_info
+            .iter()
+            .find(|info| info.image == index)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn extension(name: &str) -> String {
+        Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase)
+            .unwrap_or_else(|| "jpg".to_string())
+    }
+
+    fn stem(file_name: &str) -> String {
+        Path::new(file_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| file_name.to_string())
+    }
+
+    /// Digits needed to number `count` items, and never fewer than three.
+    fn digits(count: usize) -> usize {
+        count.to_string().len().max(3)
+    }
+
+    /// Makes a title safe to use as a file or folder name.
+    fn folder_name(title: &str) -> String {
+        let name: String = title
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+        let name = name.trim().trim_end_matches('.').to_string();
+
+        if name.is_empty() {
+            "Untitled".to_string()
+        } else {
+            name
+        }
+    }
+}
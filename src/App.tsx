@@ -4,7 +4,7 @@ import { Card, MangaViewer, Navbar } from "./components/index";
 import { Upload } from "lucide-react";
 import { listen } from "@tauri-apps/api/event";
 import { invoke } from "@tauri-apps/api/core";
-import { Comic } from "./types";
+import { Comic, ImportReport, JobEvent } from "./types";
 import { toast } from "sonner";
 
 function App() {
@@ -12,6 +12,7 @@ function App() {
   const [files, setFiles] = useState<string[]>([]);
   const [selectedComic, setSelectedComic] = useState<Comic | null>(null);
   const [isDragEntered, setIsDragEntered] = useState(false);
+  const [isLoading, setIsLoading] = useState(true);
 
   const listFiles = useCallback(async (): Promise<string[]> => {
     try {
@@ -22,13 +23,48 @@ function App() {
     }
   }, []);
 
-  const addFile = useCallback(async (filePath: string): Promise<void> => {
-    try {
-      await invoke("add_file", { sourcePath: filePath });
-    } catch (error) {
-      toast.error("Error adding file");
-      console.error("Error adding file:", error);
+  const onImportProgress = useCallback(async (job: JobEvent) => {
+    const { items_done, items_total } = job.progress;
+
+    if (job.status === "queued" || job.status === "running") {
+      toast.loading(`Importing ${items_done} of ${items_total}`, {
+        id: job.id,
+        description: job.progress.current_item ?? undefined,
+        action: {
+          label: "Cancel",
+          onClick: () => invoke("cancel_job", { id: job.id }),
+        },
+      });
+      return;
+    }
+
+    const report = await invoke<ImportReport>("get_import_report", { id: job.id });
+    const imported = report.imported.length;
+    const skipped = report.skipped.length;
+    const problems = [...report.failed, ...report.skipped, ...report.flagged]
+      .slice(0, 3)
+      .map((issue) => issue.message)
+      .join("\n");
+    const description = problems || undefined;
+
+    if (job.status === "cancelled") {
+      toast.info(`Import cancelled after ${imported} files`, { id: job.id, description });
+    } else if (report.failed.length > 0) {
+      toast.error(
+        `Imported ${imported} of ${items_total} files, ${report.failed.length} failed`,
+        { id: job.id, description },
+      );
+    } else if (skipped > 0) {
+      toast.info(`Imported ${imported} files, skipped ${skipped}`, { id: job.id, description });
+    } else if (report.flagged.length > 0) {
+      toast.info(
+        `Imported ${imported} files, ${report.flagged.length} look like duplicates`,
+        { id: job.id, description },
+      );
+    } else {
+      toast.success(`Imported ${imported} files`, { id: job.id });
     }
+    refreshFiles();
   }, []);
 
   async function refreshFiles() {
@@ -40,6 +76,18 @@ function App() {
   useEffect(() => {
     refreshFiles();
 
+    const unlistenLoaded = listen("library-loaded", () => {
+      setIsLoading(false);
+      refreshFiles();
+    });
+    // The library may have finished loading before the listener was set up
+    invoke<boolean>("is_library_loading").then((loading) => {
+      if (!loading) {
+        setIsLoading(false);
+        refreshFiles();
+      }
+    });
+
     const unlisten = listen("tauri://drag-drop", (event) => {
       const paths = (event.payload as { paths: string[] })?.paths;
       handleFileDrop(paths);
@@ -53,10 +101,18 @@ function App() {
       setIsDragEntered(false);
     });
 
+    const unlistenJobs = listen<JobEvent>("job-progress", (event) => {
+      if (event.payload.kind === "import") {
+        onImportProgress(event.payload);
+      }
+    });
+
     return () => {
       unlisten.then((fn) => fn());
       unlistenCancelled.then((fn) => fn());
       unlistenHover.then((fn) => fn());
+      unlistenJobs.then((fn) => fn());
+      unlistenLoaded.then((fn) => fn());
     };
   }, []);
 
@@ -64,8 +120,7 @@ function App() {
     setIsDragEntered(false);
 
     try {
-      await Promise.all(paths.map((file) => addFile(file)));
-      await refreshFiles();
+      await invoke("start_import", { sourcePaths: paths });
     } catch (error) {
       console.error("Error adding files:", error);
       toast.error("Error adding files");
@@ -77,10 +132,13 @@ function App() {
       <Navbar setSearchTerm={setSearchTerm} />
 
       <div className="flex-1 flex flex-row flex-wrap justify-center content-start gap-4 p-4 overflow-y-auto bg-accent">
-        {files.length === 0 && (
+        {files.length === 0 && isLoading && (
+          <div className="text-center mt-20">Loading library...</div>
+        )}
+        {files.length === 0 && !isLoading && (
           <div className="text-center mt-20">
-            No manga files found. Drag and drop your .cbz, .zip, .cbr, .rar
-            files to get started.
+            No manga files found. Drag and drop your .cbz, .zip, .cbr, .rar,
+            .cb7, .7z, .pdf files or folders to get started.
           </div>
         )}
         {files.map((fileName) => (
@@ -97,9 +155,9 @@ function App() {
       {isDragEntered && (
         <div className="absolute inset-0 backdrop-blur-sm border-4 border-dashed flex flex-col items-center justify-center z-50 pointer-events-none">
           <Upload size={80} className="mb-4 animate-bounce" />
-          <p className="text-2xl font-bold">Drop your manga files here</p>
+          <p className="text-2xl font-bold">Drop your manga files or folders here</p>
           <p className="text-lg mt-2">
-            Supported formats: .cbz, .zip, .cbr, .rar
+            Supported formats: .cbz, .zip, .cbr, .rar, .cb7, .7z, .pdf
           </p>
         </div>
       )}
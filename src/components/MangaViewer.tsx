@@ -7,7 +7,15 @@ import {
 } from "lucide-react";
 import { useCallback, useEffect, useRef, useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import { Comic } from "../types";
+import { listen } from "@tauri-apps/api/event";
+import {
+  Comic,
+  ComicSettings,
+  Config,
+  ReadingDirection,
+  ReadingSession,
+  ReadingState,
+} from "../types";
 import { Button } from "./ui/button";
 import { Slider } from "./ui/slider";
 import {
@@ -24,7 +32,14 @@ interface MangaViewerProps {
 }
 
 const CONTROLS_HIDE_DELAY = 1200;
-const PRELOAD_OFFSET = 1;
+// Matches the backend default until the config has loaded
+const DEFAULT_PREFETCH_WINDOW = 2;
+
+// Pages are resized by the backend to the physical size of the window
+const viewportSize = () => ({
+  width: Math.round(window.innerWidth * window.devicePixelRatio),
+  height: Math.round(window.innerHeight * window.devicePixelRatio),
+});
 
 export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
   const [currentPage, setCurrentPage] = useState(0);
@@ -34,8 +49,20 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
   const [isLoading, setIsLoading] = useState(false);
   const [isFullscreen, setIsFullscreen] = useState(false);
   const [showControls, setShowControls] = useState(true);
+  const [readingDirection, setReadingDirection] =
+    useState<ReadingDirection>("ltr");
+  const [prefetchWindow, setPrefetchWindow] = useState(
+    DEFAULT_PREFETCH_WINDOW,
+  );
 
   const mouseTimeoutRef = useRef<NodeJS.Timeout | null>(null);
+  const progressReadyRef = useRef(false);
+  const sessionRef = useRef<{
+    id: string;
+    page: number;
+    pageOpenedAt: number;
+  } | null>(null);
+  const currentPageRef = useRef<number | null>(null);
 
   // ---------------- Image Loading and Caching ----------------
 
@@ -54,6 +81,8 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
         const imageData = await invoke<string>("load_image_by_index", {
           cbzPath: comic.fileName,
           imageIndex: index,
+          ...viewportSize(),
+          fitMode: "contain",
         });
         setCurrentImage(imageData);
 
@@ -79,6 +108,8 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
         const imageData = await invoke<string>("load_image_by_index", {
           cbzPath: comic.fileName,
           imageIndex: index,
+          ...viewportSize(),
+          fitMode: "contain",
         });
         setCache((prevCache) => new Map(prevCache).set(index, imageData));
       } catch (error) {
@@ -89,6 +120,32 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
     [comic, cache],
   );
 
+  // Preloads the pages after `index` in the direction of `step`
+  const preloadAhead = useCallback(
+    (index: number, step: 1 | -1, pageCount: number = totalPages) => {
+      for (let offset = 1; offset <= prefetchWindow; offset++) {
+        const page = index + step * offset;
+        if (page < 0 || page >= pageCount) break;
+        preloadPage(page);
+      }
+    },
+    [prefetchWindow, totalPages, preloadPage],
+  );
+
+  // ---------------- Reading Session ----------------
+
+  const recordPageView = useCallback(() => {
+    const session = sessionRef.current;
+    if (!session) return;
+
+    const seconds = (Date.now() - session.pageOpenedAt) / 1000;
+    invoke("record_page_view", {
+      sessionId: session.id,
+      page: session.page,
+      seconds,
+    }).catch((error) => console.error("Error recording page view:", error));
+  }, []);
+
   // ---------------- Navigation ----------------
 
   const nextPage = useCallback(() => {
@@ -96,21 +153,15 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
 
     const nextIndex = currentPage + 1;
     setCurrentPage(nextIndex);
-
-    if (nextIndex + 1 < totalPages) {
-      preloadPage(nextIndex + PRELOAD_OFFSET);
-    }
-  }, [currentPage, totalPages, preloadPage]);
+    preloadAhead(nextIndex, 1);
+  }, [currentPage, totalPages, preloadAhead]);
 
   const previousPage = useCallback(() => {
     if (currentPage <= 0) return;
     const previousIndex = currentPage - 1;
     setCurrentPage(previousIndex);
-
-    if (previousIndex - 1 >= 0) {
-      preloadPage(previousIndex - PRELOAD_OFFSET);
-    }
-  }, [currentPage, preloadPage]);
+    preloadAhead(previousIndex, -1);
+  }, [currentPage, preloadAhead]);
 
   const goToPage = useCallback(
     (page: number) => {
@@ -120,6 +171,15 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
     [totalPages],
   );
 
+  // Right-to-left comics advance with the left arrow and the left button
+  const isRightToLeft = readingDirection === "rtl";
+  const pageLeft = isRightToLeft ? nextPage : previousPage;
+  const pageRight = isRightToLeft ? previousPage : nextPage;
+  const isFirstPage = currentPage === 0;
+  const isLastPage = currentPage === totalPages - 1;
+  const leftDisabled = isRightToLeft ? isLastPage : isFirstPage;
+  const rightDisabled = isRightToLeft ? isFirstPage : isLastPage;
+
   // ---------------- Fullscreen ----------------
 
   const toggleFullscreen = useCallback(() => {
@@ -146,25 +206,87 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
 
   // ---------------- Effects ----------------
 
+  // Read the prefetch window and follow changes saved from any window
+  useEffect(() => {
+    invoke<Config>("load_config")
+      .then((config) => setPrefetchWindow(config.reader.prefetch_window))
+      .catch((error) => console.error("Error loading config:", error));
+
+    const unlisten = listen<Config>("config-changed", (event) => {
+      setPrefetchWindow(event.payload.reader.prefetch_window);
+    });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
   // Load manga when it changes (new manga opened)
   useEffect(() => {
     if (!comic) return;
 
+    // Set by the cleanup, so work finishing after the comic is closed or
+    // switched does not touch the next one
+    let cancelled = false;
+
+    const startSession = async (startPage: number) => {
+      try {
+        const session = await invoke<ReadingSession>("start_reading_session", {
+          cbzPath: comic.fileName,
+        });
+        if (cancelled) {
+          await invoke("end_reading_session", { sessionId: session.id });
+          return;
+        }
+        sessionRef.current = {
+          id: session.id,
+          page: currentPageRef.current ?? startPage,
+          pageOpenedAt: Date.now(),
+        };
+      } catch (error) {
+        console.error("Error starting reading session:", error);
+      }
+    };
+
     const initializeManga = async () => {
       try {
+        progressReadyRef.current = false;
         setCache(new Map());
         setCurrentImage(null);
         setCurrentPage(0);
+        setReadingDirection("ltr");
+
+        const [pageCount, readingState, settings] = await Promise.all([
+          invoke<number>("get_page_count", { cbzPath: comic.fileName }),
+          invoke<ReadingState>("get_reading_state", {
+            cbzPath: comic.fileName,
+          }),
+          invoke<ComicSettings>("resolve_comic_settings", {
+            cbzPath: comic.fileName,
+          }).catch((error) => {
+            console.error("Error resolving comic settings:", error);
+            return null;
+          }),
+        ]);
+        if (cancelled) return;
+
+        if (settings) {
+          setReadingDirection(settings.reading_direction);
+        }
 
-        const pageCount = await invoke<number>("get_page_count", {
-          cbzPath: comic.fileName,
-        });
+        const startPage =
+          readingState.current_page < pageCount ? readingState.current_page : 0;
 
         setTotalPages(pageCount);
-        await loadPage(0);
+        setCurrentPage(startPage);
+        progressReadyRef.current = true;
+
+        // The session is bookkeeping only and never holds up the first page
+        startSession(startPage);
+        await loadPage(startPage);
 
-        if (pageCount > 1) {
-          await preloadPage(1);
+        if (!cancelled) {
+          preloadAhead(startPage, 1, pageCount);
         }
       } catch (error) {
         console.error("Error loading manga:", error);
@@ -172,6 +294,20 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
     };
 
     initializeManga();
+
+    return () => {
+      cancelled = true;
+      currentPageRef.current = null;
+
+      const session = sessionRef.current;
+      if (!session) return;
+
+      recordPageView();
+      sessionRef.current = null;
+      invoke("end_reading_session", { sessionId: session.id }).catch((error) =>
+        console.error("Error ending reading session:", error),
+      );
+    };
   }, [comic?.fileName]);
 
   // Load current page
@@ -181,15 +317,37 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
     }
   }, [currentPage, totalPages, loadPage]);
 
+  // Save reading progress
+  useEffect(() => {
+    if (!comic || !progressReadyRef.current || totalPages === 0) return;
+
+    currentPageRef.current = currentPage;
+    const session = sessionRef.current;
+    if (session && session.page !== currentPage) {
+      recordPageView();
+      sessionRef.current = {
+        ...session,
+        page: currentPage,
+        pageOpenedAt: Date.now(),
+      };
+    }
+
+    invoke("update_reading_progress", {
+      cbzPath: comic.fileName,
+      page: currentPage,
+      pageCount: totalPages,
+    }).catch((error) => console.error("Error saving progress:", error));
+  }, [comic?.fileName, currentPage, totalPages]);
+
   // Keyboard shortcuts
   useEffect(() => {
     const handleKeyPress = (e: KeyboardEvent) => {
       switch (e.key) {
         case "ArrowRight":
-          nextPage();
+          pageRight();
           break;
         case "ArrowLeft":
-          previousPage();
+          pageLeft();
           break;
         case "Escape":
           if (isFullscreen) {
@@ -210,8 +368,8 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
   }, [
     currentPage,
     isFullscreen,
-    nextPage,
-    previousPage,
+    pageLeft,
+    pageRight,
     onClose,
     toggleFullscreen,
   ]);
@@ -323,8 +481,8 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
       </div>
 
       <Button
-        onClick={previousPage}
-        disabled={currentPage === 0}
+        onClick={pageLeft}
+        disabled={leftDisabled}
         variant="ghost"
         size="icon-lg"
         style={
@@ -337,18 +495,16 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
             : {}
         }
         className={`absolute left-4 top-1/2 -translate-y-1/2 p-3 rounded-full transition-all z-10 ${
-          currentPage === 0
-            ? "opacity-30 cursor-not-allowed"
-            : "hover:scale-110"
+          leftDisabled ? "opacity-30 cursor-not-allowed" : "hover:scale-110"
         }`}
-        aria-label="Previous page"
+        aria-label={isRightToLeft ? "Next page" : "Previous page"}
       >
         <ChevronLeft size={32} />
       </Button>
 
       <Button
-        onClick={nextPage}
-        disabled={currentPage === totalPages - 1}
+        onClick={pageRight}
+        disabled={rightDisabled}
         variant="ghost"
         size="icon-lg"
         style={
@@ -361,11 +517,9 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
             : {}
         }
         className={`absolute right-4 top-1/2 -translate-y-1/2 p-3 rounded-full transition-all z-10 ${
-          currentPage === totalPages - 1
-            ? "opacity-30 cursor-not-allowed"
-            : "hover:scale-110"
+          rightDisabled ? "opacity-30 cursor-not-allowed" : "hover:scale-110"
         }`}
-        aria-label="Next page"
+        aria-label={isRightToLeft ? "Previous page" : "Next page"}
       >
         <ChevronRight size={32} />
       </Button>
@@ -378,6 +532,7 @@ export default function MangaViewer({ comic, onClose }: MangaViewerProps) {
             step={1}
             value={[currentPage]}
             onValueChange={(value) => goToPage(value[0])}
+            inverted={isRightToLeft}
             className="w-full max-w-2xl cursor-pointer"
             aria-label="Page slider"
           />
@@ -1,4 +1,6 @@
 import { createContext, useContext, useEffect, useState } from "react";
+import { listen } from "@tauri-apps/api/event";
+import { Config } from "../types";
 
 type Theme = "dark" | "light" | "system";
 
@@ -48,6 +50,18 @@ export function ThemeProvider({
     root.classList.add(theme);
   }, [theme]);
 
+  // Follow theme changes saved from any window
+  useEffect(() => {
+    const unlisten = listen<Config>("config-changed", (event) => {
+      localStorage.setItem(storageKey, event.payload.theme);
+      setTheme(event.payload.theme);
+    });
+
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [storageKey]);
+
   const value = {
     theme,
     setTheme: (theme: Theme) => {
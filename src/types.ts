@@ -1,4 +1,46 @@
-export interface Config {}
+export type ReadingDirection = "ltr" | "rtl" | "vertical";
+export type FitMode = "contain" | "cover" | "width" | "height";
+
+export interface ViewPreferences {
+  reading_direction?: ReadingDirection | null;
+  fit_mode?: FitMode | null;
+  page_mode?: "single" | "double" | null;
+  zoom?: number | null;
+  crop_borders?: boolean | null;
+  crop_tolerance?: number | null;
+  split_spreads?: boolean | null;
+  spread_threshold?: number | null;
+  spread_offset?: number | null;
+}
+
+export interface ComicSettings {
+  reading_direction: ReadingDirection;
+  fit_mode: FitMode;
+  page_mode: "single" | "double";
+  zoom: number;
+  crop_borders: boolean;
+  crop_tolerance: number;
+  split_spreads: boolean;
+  spread_threshold: number;
+  spread_offset: number;
+}
+
+export interface Config {
+  version: number;
+  library: {
+    paths: string[];
+    import_mode: "copy" | "move";
+  };
+  theme: "system" | "light" | "dark";
+  reader: {
+    default_view: ViewPreferences;
+    prefetch_window: number;
+  };
+  cache: {
+    page_cache_mb: number;
+  };
+  key_bindings: Record<string, string>;
+}
 
 export interface Metadata {
   title: string;
@@ -12,6 +54,190 @@ export interface Metadata {
   writer: string;
   publisher: string;
   page_count: string;
+  manga?: string;
+}
+
+export type ReadStatus = "unread" | "in_progress" | "read";
+
+export interface ReadingState {
+  status: ReadStatus;
+  current_page: number;
+  furthest_page: number;
+  page_count: number;
+  started_at: string | null;
+  finished_at: string | null;
+  last_read_at: string | null;
+  read_count: number;
+}
+
+export interface ReadingSession {
+  id: string;
+  file_name: string;
+  series: string;
+  started_at: string;
+  ended_at: string | null;
+  pages_turned: number;
+  completed: boolean;
+}
+
+export type ErrorCode =
+  | "ARCHIVE_CORRUPT"
+  | "UNSUPPORTED_FORMAT"
+  | "DUPLICATE"
+  | "NOT_FOUND"
+  | "METADATA_PARSE"
+  | "ENCODE"
+  | "TASK_FAILED"
+  | "INVALID_CONFIG"
+  | "INVALID_INPUT"
+  | "PARTIAL_FAILURE"
+  | "IO"
+  | "INTERNAL";
+
+export interface AppError {
+  code: ErrorCode;
+  message: string;
+  path: string | null;
+  source: string[];
+  fields?: { field: string; message: string }[];
+  failures?: { path: string; code: ErrorCode; message: string }[];
+}
+
+export function isAppError(error: unknown): error is AppError {
+  return typeof error === "object" && error !== null && "code" in error;
+}
+
+export type JobKind = "import" | "scan" | "rebuild_thumbnails" | "verify" | "convert" | "optimize";
+
+export type JobStatus = "queued" | "running" | "completed" | "failed" | "cancelled";
+
+export interface JobProgress {
+  items_done: number;
+  items_total: number;
+  bytes_done: number;
+  bytes_total: number;
+  current_item: string | null;
+}
+
+export type JobItemStatus = "done" | "skipped" | "failed";
+
+export interface JobItemResult {
+  item: string;
+  status: JobItemStatus;
+  error: { code: ErrorCode; message: string } | null;
+  note: string | null;
+}
+
+export interface Job {
+  id: string;
+  kind: JobKind;
+  status: JobStatus;
+  progress: JobProgress;
+  results: JobItemResult[];
+  created_at: string;
+  finished_at: string | null;
+}
+
+export interface JobEvent {
+  id: string;
+  kind: JobKind;
+  status: JobStatus;
+  progress: JobProgress;
+  skipped: number;
+  failed: number;
+}
+
+export interface ImportIssue {
+  path: string;
+  code: ErrorCode;
+  message: string;
+}
+
+export interface ImportReport {
+  imported: string[];
+  flagged: ImportIssue[];
+  skipped: ImportIssue[];
+  failed: ImportIssue[];
+}
+
+export type DuplicateKind = "exact" | "repack" | "near";
+
+export interface DuplicateCandidate {
+  file_name: string;
+  size: number;
+  page_count: number;
+}
+
+export interface DuplicateGroup {
+  kind: DuplicateKind;
+  similarity: number;
+  comics: DuplicateCandidate[];
+}
+
+export type IntegrityIssueKind =
+  | "unreadable"
+  | "checksum"
+  | "decode"
+  | "page_count"
+  | "no_pages";
+
+export interface IntegrityIssue {
+  kind: IntegrityIssueKind;
+  entry: string | null;
+  message: string;
+}
+
+export interface IntegrityReport {
+  archive_size: number;
+  archive_modified: number;
+  checked_at: string;
+  entries: number;
+  pages: number;
+  declared_pages: number | null;
+  issues: IntegrityIssue[];
+}
+
+export interface RepairReport {
+  recovered: number;
+  dropped: string[];
+  backup: string | null;
+  report: IntegrityReport;
+}
+
+export type TargetFormat = "png" | "webp" | "jpeg";
+
+export interface OptimizeOptions {
+  format: TargetFormat;
+  quality: number;
+  max_height: number | null;
+  strip_metadata: boolean;
+}
+
+export interface OptimizeReport {
+  options: OptimizeOptions;
+  archive_size: number;
+  archive_modified: number;
+  optimized_size: number;
+  saved_bytes: number;
+  pages: number;
+  pages_changed: number;
+  pages_resized: number;
+  created_at: string;
+}
+
+export type DocumentFormat = "pdf" | "epub";
+
+export interface ExtractOptions {
+  first_page?: number;
+  last_page?: number;
+  format?: TargetFormat;
+  quality?: number;
+}
+
+export interface MergeOptions {
+  title: string;
+  volume: string;
+  chapter_folders: boolean;
 }
 
 export interface Comic {